@@ -0,0 +1,91 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+/// A strong handle whose allocation can be identified and whose value can
+/// be read. Implemented by both `PinRc` and `PinArc`.
+pub trait SharedHandle {
+    type Target: ?Sized;
+    /// The address of the allocation, which is shared by all of its handles.
+    fn addr(this: &Self) -> usize;
+    /// Call `f` with a shared reference to the value.
+    fn with_value<R, F: FnOnce(&Self::Target) -> R>(this: &Self, f: F) -> R;
+}
+
+/// Compares and hashes a handle by the address of its allocation, so that
+/// two handles are only equal if they share a value.
+#[derive(Clone, Debug)]
+pub struct ByPtr<P>(pub P);
+
+impl<P: SharedHandle> PartialEq for ByPtr<P> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        P::addr(&self.0) == P::addr(&other.0)
+    }
+}
+
+impl<P: SharedHandle> Eq for ByPtr<P> {}
+
+impl<P: SharedHandle> PartialOrd for ByPtr<P> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P: SharedHandle> Ord for ByPtr<P> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        P::addr(&self.0).cmp(&P::addr(&other.0))
+    }
+}
+
+impl<P: SharedHandle> Hash for ByPtr<P> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        P::addr(&self.0).hash(state)
+    }
+}
+
+/// Compares and hashes a handle by its value, borrowing or read-locking it
+/// for the duration of each comparison.
+///
+/// As with any `HashMap` or `BTreeMap` key, it is a logic error for the
+/// value to be modified through another handle while it is used as a key,
+/// as the map will then be unable to find or order it correctly.
+///
+/// Handles which share an allocation are equal without reading the value,
+/// so a value is never borrowed twice at once. This relies on the value's
+/// equality being reflexive, so the comparisons require `Eq` and `Ord`.
+#[derive(Clone, Debug)]
+pub struct ByValue<P>(pub P);
+
+impl<P: SharedHandle> PartialEq for ByValue<P> where P::Target: Eq {
+    fn eq(&self, other: &Self) -> bool {
+        P::addr(&self.0) == P::addr(&other.0) ||
+            P::with_value(&self.0, |a| P::with_value(&other.0, |b| a == b))
+    }
+}
+
+impl<P: SharedHandle> Eq for ByValue<P> where P::Target: Eq {}
+
+impl<P: SharedHandle> PartialOrd for ByValue<P> where P::Target: Ord {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P: SharedHandle> Ord for ByValue<P> where P::Target: Ord {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if P::addr(&self.0) == P::addr(&other.0) {
+            return Ordering::Equal;
+        }
+        P::with_value(&self.0, |a| P::with_value(&other.0, |b| a.cmp(b)))
+    }
+}
+
+impl<P: SharedHandle> Hash for ByValue<P> where P::Target: Hash {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        P::with_value(&self.0, |value| value.hash(state))
+    }
+}
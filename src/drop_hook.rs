@@ -0,0 +1,85 @@
+use std::mem::Pin;
+use std::ops::Deref;
+use std::marker::Unpin;
+use std::fmt;
+
+use pin_arc::PinArc;
+
+/// A value with a callback which is run just before the value is dropped,
+/// while it is still pinned in place. See `PinArc::with_drop_hook`.
+pub struct DropHook<T> {
+    hook: Box<FnMut(Pin<T>) + Send>,
+    value: T,
+}
+
+// The hook is only ever accessed through `&mut self`, when it is dropped
+unsafe impl<T: Sync> Sync for DropHook<T> {}
+
+// Otherwise a pinned `DropHook` of an `Unpin` value could be replaced or
+// moved out, running the hook while handles to it are still alive
+impl<T> !Unpin for DropHook<T> {}
+
+impl<T> DropHook<T> {
+    pub fn new<F>(value: T, hook: F) -> DropHook<T>
+        where F: FnOnce(Pin<T>) + Send + 'static
+    {
+        let mut hook = Some(hook);
+        DropHook {
+            hook: Box::new(move |pin| if let Some(hook) = hook.take() { hook(pin) }),
+            value,
+        }
+    }
+
+    /// Get a pinned reference to the value.
+    #[inline]
+    pub fn as_pin<'a>(mut this: Pin<'a, DropHook<T>>) -> Pin<'a, T> {
+        unsafe {
+            let this = Pin::get_mut(&mut this) as *mut DropHook<T>;
+            Pin::new_unchecked(&mut (*this).value)
+        }
+    }
+}
+
+impl<T> Deref for DropHook<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Drop for DropHook<T> {
+    fn drop(&mut self) {
+        // The value is dropped in place straight afterwards, so it remains
+        // pinned for the whole call
+        (self.hook)(unsafe { Pin::new_unchecked(&mut self.value) });
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DropHook<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DropHook").field("value", &self.value).finish()
+    }
+}
+
+impl<T> PinArc<DropHook<T>> {
+    /// Allocate and pin a value, along with a hook which is run when the
+    /// value is dropped, once the last strong reference to it has gone.
+    ///
+    /// The hook is given a pinned reference to the value, which is dropped
+    /// in place as soon as the hook returns. `DropHook` is never `Unpin`,
+    /// even if `T` is, so the value cannot be replaced or moved out through
+    /// the handles, and the hook cannot run any earlier:
+    ///
+    /// ```compile_fail
+    /// # use pin_rc::{PinArc, DropHook};
+    /// let arc = PinArc::with_drop_hook(1, |_| {});
+    /// *arc.write().unwrap() = DropHook::new(2, |_| {});
+    /// ```
+    pub fn with_drop_hook<F>(data: T, hook: F) -> PinArc<DropHook<T>>
+        where F: FnOnce(Pin<T>) + Send + 'static
+    {
+        PinArc::new(DropHook::new(data, hook))
+    }
+}
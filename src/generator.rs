@@ -0,0 +1,158 @@
+//! Safe resumption of pinned generators.
+
+use std::ops::{Generator, GeneratorState};
+use std::mem::Pin;
+use std::thread::{self, JoinHandle};
+use std::panic;
+
+use pin_rc::PinRefMut;
+use pin_arc::{PinArc, PinRwLockWriteGuard};
+
+/// Resuming a generator is unsafe, because it must not be moved once it has
+/// started. A pinned generator can never be moved, so it is always safe to
+/// resume.
+pub trait SafeGenerator {
+    type Yield;
+    type Return;
+    fn resume(&mut self) -> GeneratorState<Self::Yield, Self::Return>;
+}
+
+impl<'a, T: Generator + ?Sized> SafeGenerator for Pin<'a, T> {
+    type Yield = T::Yield;
+    type Return = T::Return;
+    fn resume(&mut self) -> GeneratorState<Self::Yield, Self::Return> {
+        unsafe { Pin::get_mut(self).resume() }
+    }
+}
+
+impl<'a, T: Generator + ?Sized> PinRefMut<'a, T> {
+    /// Pin the borrowed generator and resume it.
+    #[inline]
+    pub fn resume(&mut self) -> GeneratorState<T::Yield, T::Return> {
+        self.as_pin().resume()
+    }
+}
+
+impl<'a, T: Generator + ?Sized> PinRwLockWriteGuard<'a, T> {
+    /// Pin the locked generator and resume it.
+    #[inline]
+    pub fn resume(&mut self) -> GeneratorState<T::Yield, T::Return> {
+        self.as_pin().resume()
+    }
+}
+
+impl<T> PinArc<T>
+    where T: Generator + Send + Sync + 'static, T::Return: Send + 'static
+{
+    /// Spawn a thread which runs the generator to completion, passing each
+    /// yielded value to `on_yield`. The lock is only held while resuming,
+    /// not while `on_yield` runs.
+    ///
+    /// The returned handle can be joined to get the generator's return
+    /// value. The thread panics if the lock is poisoned.
+    pub fn spawn_driver<F>(this: Self, mut on_yield: F) -> JoinHandle<T::Return>
+        where F: FnMut(T::Yield) + Send + 'static
+    {
+        thread::spawn(move || loop {
+            let state = this.write().expect("PinArc lock poisoned").resume();
+            match state {
+                GeneratorState::Yielded(value) => on_yield(value),
+                GeneratorState::Complete(value) => return value,
+            }
+        })
+    }
+}
+
+impl<G> PinArc<Vec<Option<Box<G>>>>
+    where G: Generator<Return = ()> + ?Sized
+{
+    /// Resume every generator once while holding the lock, returning the
+    /// value each one yielded.
+    ///
+    /// Generators which complete are dropped, leaving `None` in their slot,
+    /// and are skipped from then on. Their entries in the result are `None`.
+    /// Panics if the lock is poisoned.
+    pub fn tick_all(&self) -> Vec<Option<G::Yield>> {
+        let mut guard = self.write().expect("PinArc lock poisoned");
+        let mut pin = guard.as_pin();
+        // Each generator is boxed, so it is never moved by mutating the `Vec`
+        let slots = unsafe { Pin::get_mut(&mut pin) };
+        slots.iter_mut().map(|slot| unsafe { tick_slot(slot) }).collect()
+    }
+}
+
+impl<G> PinArc<Vec<Option<G>>>
+    where G: Generator<Return = ()> + Send + 'static, G::Yield: Send + 'static
+{
+    /// Like `tick_all`, but the generators are resumed on up to `threads`
+    /// worker threads, each of which takes a contiguous share of the slots.
+    ///
+    /// The lock is held until every worker has finished, and each worker
+    /// only has access to its own slots. Every worker is joined before this
+    /// returns or panics, and if any generator panics, the panic is
+    /// propagated once all of the workers have been joined. Panics if
+    /// `threads` is zero.
+    pub fn par_tick(&self, threads: usize) -> Vec<Option<G::Yield>> {
+        assert!(threads > 0, "par_tick needs at least one thread");
+        let mut guard = self.write().expect("PinArc lock poisoned");
+        let mut pin = guard.as_pin();
+        // The `Vec` is only used to find the slots, so its elements never move
+        let slots = unsafe { Pin::get_mut(&mut pin) };
+        if slots.is_empty() {
+            return Vec::new();
+        }
+
+        let chunk_size = (slots.len() + threads - 1) / threads;
+        let mut workers = Workers(Vec::with_capacity(threads));
+        for chunk in slots.chunks_mut(chunk_size) {
+            // The chunk outlives the worker, because every worker is joined
+            // before the lock is released, even when unwinding
+            let chunk: &'static mut [Option<G>] = unsafe { &mut *(chunk as *mut _) };
+            let handle = thread::Builder::new()
+                .spawn(move || {
+                    chunk.iter_mut().map(|slot| unsafe { tick_slot(slot) }).collect::<Vec<_>>()
+                })
+                .unwrap_or_else(|e| panic!("failed to spawn thread: {}", e));
+            workers.0.push(handle);
+        }
+
+        let results: Vec<_> = workers.0.drain(..).map(JoinHandle::join).collect();
+        let mut values = Vec::with_capacity(slots.len());
+        for result in results {
+            values.extend(result.unwrap_or_else(|e| panic::resume_unwind(e)));
+        }
+        values
+    }
+}
+
+/// Worker threads which are joined when dropped, so that none of them can
+/// outlive the data they borrow.
+struct Workers<T>(Vec<JoinHandle<T>>);
+
+impl<T> Drop for Workers<T> {
+    fn drop(&mut self) {
+        for handle in self.0.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Resume the generator in `slot` if there is one, dropping it once it
+/// completes.
+///
+/// This function is unsafe. The generator must be pinned, and so must not
+/// have been moved since it was first resumed, and must not be moved out of
+/// `slot` afterwards.
+unsafe fn tick_slot<G: Generator<Return = ()>>(slot: &mut Option<G>) -> Option<G::Yield> {
+    let state = match *slot {
+        Some(ref mut gen) => Pin::new_unchecked(gen).resume(),
+        None => return None,
+    };
+    match state {
+        GeneratorState::Yielded(value) => Some(value),
+        GeneratorState::Complete(()) => {
+            *slot = None;
+            None
+        }
+    }
+}
@@ -1,34 +1,81 @@
-#![cfg_attr(test, feature(generators, generator_trait))]
-#![feature(pin)]
+#![cfg_attr(test, feature(generators))]
+#![cfg_attr(any(test, feature = "generator"), feature(generator_trait))]
+#![feature(pin, coerce_unsized, unsize, untagged_unions, repr_transparent, optin_builtin_traits)]
+#![cfg_attr(feature = "leak-check", feature(dropck_eyepatch, generic_param_attrs))]
 
 pub use pin_rc::*;
 pub use pin_arc::*;
+pub use pin_box::*;
+pub use pin_spin_arc::*;
+pub use maybe_uninit::*;
+pub use pin_arc_swap::*;
+pub use pin_thread_local::*;
+pub use weak_value_map::*;
+pub use pin_condvar::*;
+pub use drop_hook::*;
+pub use pin_cow::*;
+pub use by_key::*;
+pub use project::*;
 
+#[macro_use]
+mod project;
 mod pin_rc;
 mod pin_arc;
+mod pin_box;
+mod pin_spin_arc;
+mod maybe_uninit;
+mod pin_arc_swap;
+mod pin_thread_local;
+mod weak_value_map;
+mod pin_condvar;
+mod drop_hook;
+mod pin_cow;
+mod by_key;
+
+#[cfg(any(test, feature = "generator"))]
+pub mod generator;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use generator::SafeGenerator;
     use std::ops::{Generator, GeneratorState};
     use std::mem::Pin;
 
-    trait SafeGenerator {
-        type Yield;
-        type Return;
-        fn resume(&mut self) -> GeneratorState<Self::Yield, Self::Return>;
+    #[test]
+    fn pin_rc_works() {
+        let gen = PinRc::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        });
+
+        let mut results = Vec::new();
+        while let GeneratorState::Yielded(x) = gen.borrow_mut().as_pin().resume() {
+            results.push(x);
+        }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
     }
 
-    impl<'a, T: Generator + ?Sized> SafeGenerator for Pin<'a, T> {
-        type Yield = T::Yield;
-        type Return = T::Return;
-        fn resume(&mut self) -> GeneratorState<Self::Yield, Self::Return> {
-            unsafe { Pin::get_mut(self).resume() }
+    #[test]
+    fn pin_arc_works() {
+        let gen = PinArc::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        });
+
+        let mut results = Vec::new();
+        while let GeneratorState::Yielded(x) = gen.write().unwrap().as_pin().resume() {
+            results.push(x);
         }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
     }
 
     #[test]
-    fn pin_rc_works() {
+    fn pin_rc_resume() {
         let gen = PinRc::new(|| {
             for i in 0..10 {
                 yield i;
@@ -36,7 +83,7 @@ mod tests {
         });
 
         let mut results = Vec::new();
-        while let GeneratorState::Yielded(x) = gen.borrow_mut().as_pin().resume() {
+        while let GeneratorState::Yielded(x) = gen.borrow_mut().resume() {
             results.push(x);
         }
 
@@ -44,7 +91,7 @@ mod tests {
     }
 
     #[test]
-    fn pin_arc_works() {
+    fn pin_arc_resume() {
         let gen = PinArc::new(|| {
             for i in 0..10 {
                 yield i;
@@ -52,10 +99,1579 @@ mod tests {
         });
 
         let mut results = Vec::new();
-        while let GeneratorState::Yielded(x) = gen.write().unwrap().as_pin().resume() {
+        while let GeneratorState::Yielded(x) = gen.write().unwrap().resume() {
+            results.push(x);
+        }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+    }
+
+    #[test]
+    fn pin_rc_into_arc() {
+        let rc = PinRc::new(5);
+        let arc = PinRc::into_arc(rc).unwrap();
+        assert_eq!(*arc.read().unwrap(), 5);
+
+        let rc = PinRc::new(6);
+        let rc2 = rc.clone();
+        let rc = PinRc::into_arc(rc).unwrap_err();
+        assert!(PinRc::ptr_eq(&rc, &rc2));
+        assert_eq!(PinRc::strong_count(&rc), 2);
+    }
+
+    #[test]
+    fn pin_arc_into_rc() {
+        let arc = PinArc::new(5);
+        let rc = PinArc::into_rc(arc).unwrap();
+        assert_eq!(*rc.borrow(), 5);
+
+        let arc = PinArc::new(6);
+        let arc2 = arc.clone();
+        let arc = PinArc::into_rc(arc).unwrap_err();
+        assert!(PinArc::ptr_eq(&arc, &arc2));
+        assert_eq!(PinArc::strong_count(&arc), 2);
+    }
+
+    #[test]
+    fn pin_box_works() {
+        let mut gen = PinBox::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        });
+
+        let mut results = Vec::new();
+        while let GeneratorState::Yielded(x) = gen.as_pin().resume() {
+            results.push(x);
+        }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+    }
+
+    #[test]
+    fn pin_box_unsize() {
+        let mut gen: PinBox<Generator<Yield = i32, Return = &str>> = PinBox::new(|| {
+            yield 1;
+            "done"
+        });
+
+        assert_eq!(gen.as_pin().resume(), GeneratorState::Yielded(1));
+        assert_eq!(gen.as_pin().resume(), GeneratorState::Complete("done"));
+    }
+
+    #[test]
+    fn pin_arc_new_children() {
+        let parent = PinArc::new("parent");
+        let children = PinArc::new_children(&parent, 0..3, |weak, i| (weak, i));
+
+        assert_eq!(children.len(), 3);
+        assert_eq!(PinArc::weak_count(&parent), 3);
+        for (i, child) in children.iter().enumerate() {
+            let guard = child.read().unwrap();
+            assert_eq!(guard.1, i);
+            assert!(PinArc::ptr_eq(&guard.0.upgrade().unwrap(), &parent));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pin-tracking")]
+    fn pin_arc_is_pin_active() {
+        let arc = PinArc::new(5);
+        assert!(!arc.is_pin_active());
+        {
+            let mut guard = arc.write().unwrap();
+            assert!(!arc.is_pin_active());
+            let _pin = guard.as_pin();
+            assert!(arc.is_pin_active());
+        }
+        assert!(!arc.is_pin_active());
+        {
+            let mut mapped = arc.write_map(|pin| pin).unwrap();
+            let _pin = mapped.as_pin();
+            assert!(arc.is_pin_active());
+        }
+        assert!(!arc.is_pin_active());
+    }
+
+    #[test]
+    fn pin_rc_format() {
+        let rc = PinRc::new(5);
+        assert_eq!(format!("{}", rc), "5");
+        {
+            let _guard = rc.borrow_mut();
+            assert_eq!(format!("{}", rc), "<borrowed>");
+        }
+
+        assert_eq!(format!("{:p}", rc), format!("{:p}", rc.clone()));
+        assert_ne!(format!("{:p}", rc), format!("{:p}", PinRc::new(5)));
+    }
+
+    #[test]
+    fn pin_arc_format() {
+        let arc = PinArc::new(5);
+        assert_eq!(format!("{}", arc), "5");
+        {
+            let _guard = arc.write().unwrap();
+            assert_eq!(format!("{}", arc), "<locked>");
+        }
+
+        assert_eq!(format!("{:p}", arc), format!("{:p}", arc.clone()));
+        assert_ne!(format!("{:p}", arc), format!("{:p}", PinArc::new(5)));
+    }
+
+    #[test]
+    fn pin_rc_from_box() {
+        // `PinRc::from` alone would be ambiguous with `From<T>` here
+        fn unbox<G>(boxed: Box<G>) -> PinRc<G> {
+            PinRc::from(boxed)
+        }
+
+        let boxed = Box::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        });
+        let gen: PinRc<_> = unbox(boxed);
+
+        let mut results = Vec::new();
+        while let GeneratorState::Yielded(x) = gen.borrow_mut().as_pin().resume() {
+            results.push(x);
+        }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+    }
+
+    #[test]
+    fn pin_arc_from_box() {
+        let arc: PinArc<i32> = PinArc::from(Box::new(5));
+        assert_eq!(*arc.read().unwrap(), 5);
+    }
+
+    #[test]
+    fn pin_spin_arc_works() {
+        let gen = PinSpinArc::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        });
+
+        let mut results = Vec::new();
+        while let GeneratorState::Yielded(x) = gen.lock().as_pin().resume() {
+            results.push(x);
+        }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+    }
+
+    #[test]
+    fn pin_spin_arc_exclusive() {
+        let arc = PinSpinArc::new(5);
+        let other = arc.clone();
+        {
+            let guard = arc.lock();
+            assert_eq!(*guard, 5);
+            assert!(other.try_lock().is_none());
+        }
+        assert!(other.try_lock().is_some());
+    }
+
+    #[test]
+    fn pin_rc_as_ref() {
+        fn bump(cell: impl AsRef<::std::cell::RefCell<u32>>) {
+            *cell.as_ref().borrow_mut() += 1;
+        }
+
+        let rc = PinRc::new(1);
+        bump(rc.clone());
+        assert_eq!(*rc.borrow(), 2);
+    }
+
+    #[test]
+    fn pin_arc_as_ref() {
+        fn bump(lock: impl AsRef<::std::sync::RwLock<u32>>) {
+            *lock.as_ref().write().unwrap() += 1;
+        }
+
+        let arc = PinArc::new(1);
+        bump(arc.clone());
+        assert_eq!(*arc.read().unwrap(), 2);
+    }
+
+    #[test]
+    fn pin_arc_as_pin_mut() {
+        let gen: Box<Generator<Yield = i32, Return = ()>> = Box::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        });
+        let state = PinArc::new(Some(gen));
+
+        let mut results = Vec::new();
+        while let GeneratorState::Yielded(x) = state.write().unwrap().as_pin_mut().unwrap().resume() {
             results.push(x);
         }
 
         assert_eq!((0..10).collect::<Vec<_>>(), results);
+
+        let empty: PinArc<Option<Box<Generator<Yield = i32, Return = ()>>>> = PinArc::new(None);
+        assert!(empty.write().unwrap().as_pin_mut().is_none());
+    }
+
+    #[test]
+    fn pin_rc_into_inner() {
+        assert_eq!(PinRc::into_inner(PinRc::new(5)), Some(5));
+
+        let rc = PinRc::new(6);
+        let rc2 = rc.clone();
+        assert_eq!(PinRc::into_inner(rc), None);
+        assert_eq!(PinRc::strong_count(&rc2), 1);
+    }
+
+    #[test]
+    fn pin_arc_into_inner() {
+        assert_eq!(PinArc::into_inner(PinArc::new(5)), Some(5));
+
+        let arc = PinArc::new(6);
+        let arc2 = arc.clone();
+        assert_eq!(PinArc::into_inner(arc), None);
+        assert_eq!(PinArc::strong_count(&arc2), 1);
+    }
+
+    #[test]
+    fn pin_arc_new_uninit() {
+        struct SelfRef {
+            value: u32,
+            ptr: *const u32,
+        }
+
+        let arc = PinArc::<SelfRef>::new_uninit();
+        {
+            let mut guard = arc.write().unwrap();
+            let ptr = MaybeUninit::pinned_ptr(&mut guard.as_pin());
+            unsafe {
+                ::std::ptr::write(&mut (*ptr).value, 5);
+                ::std::ptr::write(&mut (*ptr).ptr, &(*ptr).value);
+            }
+        }
+        let arc = unsafe { PinArc::assume_init(arc) };
+
+        let guard = arc.read().unwrap();
+        assert_eq!(guard.ptr, &guard.value as *const u32);
+        assert_eq!(unsafe { *guard.ptr }, 5);
+    }
+
+    #[test]
+    #[cfg(feature = "leak-check")]
+    #[should_panic(expected = "leaked PinArc allocations")]
+    fn pin_arc_leak_check() {
+        struct Node {
+            next: Option<PinArc<Node>>,
+        }
+
+        let tracker = LeakTracker::new();
+        // Leaked on purpose, so not reported
+        PinArc::leak(PinArc::new(Node { next: None })).ok().unwrap();
+        let a = PinArc::new(Node { next: None });
+        let b = PinArc::new(Node { next: Some(a.clone()) });
+        a.write().unwrap().as_pin().next = Some(b.clone());
+        drop((a, b));
+
+        tracker.assert_no_leaks();
+    }
+
+    #[test]
+    #[cfg(feature = "leak-check")]
+    fn pin_arc_leak_check_raw_counts() {
+        let tracker = LeakTracker::new();
+        let ptr = PinArc::into_raw(PinArc::new(5));
+        unsafe {
+            PinArc::increment_strong_count(ptr);
+            PinArc::decrement_strong_count(ptr);
+            let arc = PinArc::from_raw(ptr);
+            assert_eq!(*arc.read().unwrap(), 5);
+        }
+        let ptr = PinArc::into_raw(PinArc::new(6));
+        unsafe { PinArc::decrement_strong_count(ptr) };
+        tracker.assert_no_leaks();
+    }
+
+    #[test]
+    fn pin_rc_slice() {
+        let rc: PinRc<[i32]> = PinRc::new([1, 2, 3]);
+        assert_eq!(rc.borrow().len(), 3);
+        assert_eq!(rc.borrow()[1], 2);
+    }
+
+    #[test]
+    fn pin_arc_slice() {
+        let arc: PinArc<[i32]> = PinArc::new([1, 2, 3]);
+        assert_eq!(arc.read().unwrap().len(), 3);
+        assert_eq!(arc.read().unwrap()[1], 2);
+    }
+
+    #[test]
+    fn pin_arc_slice_get() {
+        let arc: PinArc<[i32]> = PinArc::new([1, 2, 3]);
+        let guard = arc.read().unwrap();
+        assert_eq!(guard.get(2), Some(&3));
+        assert_eq!(guard.get(3), None);
+    }
+
+    #[test]
+    fn pin_arc_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PinArc<i32>>();
+        assert_send_sync::<PinArc<Vec<String>>>();
+    }
+
+    #[test]
+    fn pin_rc_raw_round_trip() {
+        let rc = PinRc::new(5);
+        let rc2 = rc.clone();
+        let ptr = PinRc::into_raw(rc);
+        assert_eq!(PinRc::strong_count(&rc2), 2);
+
+        unsafe {
+            PinRc::increment_strong_count(ptr);
+            assert_eq!(PinRc::strong_count(&rc2), 3);
+            PinRc::decrement_strong_count(ptr);
+            assert_eq!(PinRc::strong_count(&rc2), 2);
+        }
+
+        let rc = unsafe { PinRc::from_raw(ptr) };
+        assert!(PinRc::ptr_eq(&rc, &rc2));
+        assert_eq!(*rc.borrow(), 5);
+        assert_eq!(PinRc::strong_count(&rc), 2);
+    }
+
+    #[test]
+    fn pin_arc_raw_round_trip() {
+        let arc = PinArc::new(5);
+        let arc2 = arc.clone();
+        let ptr = PinArc::into_raw(arc);
+        assert_eq!(PinArc::strong_count(&arc2), 2);
+
+        unsafe {
+            PinArc::increment_strong_count(ptr);
+            assert_eq!(PinArc::strong_count(&arc2), 3);
+            PinArc::decrement_strong_count(ptr);
+            assert_eq!(PinArc::strong_count(&arc2), 2);
+        }
+
+        let arc = unsafe { PinArc::from_raw(ptr) };
+        assert!(PinArc::ptr_eq(&arc, &arc2));
+        assert_eq!(*arc.read().unwrap(), 5);
+        assert_eq!(PinArc::strong_count(&arc), 2);
+    }
+
+    #[test]
+    fn pin_weak_with_write() {
+        let arc = PinArc::new(5);
+        let weak = PinArc::downgrade(&arc);
+
+        assert_eq!(weak.with_write(|mut value| { *value += 1; *value }), Some(6));
+        assert_eq!(*arc.read().unwrap(), 6);
+        assert_eq!(PinArc::strong_count(&arc), 1);
+
+        drop(arc);
+        assert_eq!(weak.with_write(|mut value| { *value += 1; *value }), None);
+    }
+
+    #[test]
+    fn pin_ref_mut_filter_map() {
+        fn project_some<T>(mut pin: Pin<Option<T>>) -> Option<Pin<T>> {
+            let opt: *mut Option<T> = unsafe { Pin::get_mut(&mut pin) };
+            unsafe { (*opt).as_mut().map(|value| Pin::new_unchecked(value)) }
+        }
+
+        let gen = PinRc::new(Some(|| {
+            for i in 0..10 {
+                yield i;
+            }
+        }));
+
+        let mut results = Vec::new();
+        loop {
+            let borrow = PinRefMut::filter_map(gen.borrow_mut(), project_some);
+            match borrow.ok().unwrap().resume() {
+                GeneratorState::Yielded(x) => results.push(x),
+                GeneratorState::Complete(()) => break,
+            }
+        }
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+
+        let empty: PinRc<Option<i32>> = PinRc::new(None);
+        let borrow = PinRefMut::filter_map(empty.borrow_mut(), project_some).err().unwrap();
+        assert_eq!(*borrow, None);
+    }
+
+    #[test]
+    fn pin_arc_spawn_driver() {
+        let gen = PinArc::new(|| {
+            for i in 0..10 {
+                yield i;
+            }
+            "done"
+        });
+
+        let (tx, rx) = ::std::sync::mpsc::channel();
+        let driver = PinArc::spawn_driver(gen, move |x| tx.send(x).unwrap());
+
+        assert_eq!(driver.join().unwrap(), "done");
+        assert_eq!((0..10).collect::<Vec<_>>(), rx.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pin_arc_ord_by_ptr() {
+        use std::collections::BTreeSet;
+
+        let a = PinArc::new(5);
+        let b = PinArc::new(5);
+        let _guard = a.write().unwrap();
+
+        let set: BTreeSet<_> = vec![a.clone(), b.clone(), a.clone()].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&a));
+        assert!(set.contains(&b));
+        assert!(a != b);
+
+        let ordered: Vec<_> = set.into_iter().collect();
+        assert!(ordered[0] < ordered[1]);
+    }
+
+    #[test]
+    fn pin_rc_get_mut_unchecked() {
+        let mut rc = PinRc::new(0u64);
+        assert_eq!((PinRc::strong_count(&rc), PinRc::weak_count(&rc)), (1, 0));
+        for i in 0..1000 {
+            *unsafe { PinRc::get_mut_unchecked(&mut rc) } += i;
+        }
+        assert_eq!(*rc.borrow(), 499500);
+    }
+
+    #[test]
+    fn pin_arc_get_mut_unchecked() {
+        let mut arc = PinArc::new(0u64);
+        assert_eq!((PinArc::strong_count(&arc), PinArc::weak_count(&arc)), (1, 0));
+        for i in 0..1000 {
+            *unsafe { PinArc::get_mut_unchecked(&mut arc) } += i;
+        }
+        assert_eq!(*arc.read().unwrap(), 499500);
+    }
+
+    #[test]
+    #[should_panic(expected = "PinArc")]
+    fn pin_arc_get_mut_unchecked_shared() {
+        let mut arc = PinArc::new(0u64);
+        let _weak = PinArc::downgrade(&arc);
+        *unsafe { PinArc::get_mut_unchecked(&mut arc) } += 1;
+    }
+
+    #[test]
+    fn pin_arc_swap_consistent() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let swap = Arc::new(PinArcSwap::new(PinArc::new((0, 0))));
+
+        let old = swap.load();
+        swap.store(PinArc::new((1, 2)));
+        assert_eq!(*old.read().unwrap(), (0, 0));
+        let new = swap.load();
+        assert_eq!(*new.read().unwrap(), (1, 2));
+
+        let writer = {
+            let swap = swap.clone();
+            thread::spawn(move || {
+                for i in 2..1000 {
+                    swap.store(PinArc::new((i, i * 2)));
+                }
+            })
+        };
+        for _ in 0..1000 {
+            let config = swap.load();
+            let guard = config.read().unwrap();
+            assert_eq!(guard.1, guard.0 * 2);
+        }
+        writer.join().unwrap();
+
+        let last = swap.load();
+        assert_eq!(*last.read().unwrap(), (999, 1998));
+    }
+
+    #[test]
+    fn pin_weak_upgrade_or_else() {
+        let mut rebuilds = 0;
+        let arc = PinArc::new(5);
+        let weak = PinArc::downgrade_and(&arc, |weak| weak);
+
+        let same = weak.upgrade_or_else(|| { rebuilds += 1; PinArc::new(6) });
+        assert!(PinArc::ptr_eq(&same, &arc));
+        assert_eq!(rebuilds, 0);
+
+        drop((arc, same));
+        let rebuilt = weak.upgrade_or_else(|| { rebuilds += 1; PinArc::new(6) });
+        assert_eq!(*rebuilt.read().unwrap(), 6);
+        assert_eq!(rebuilds, 1);
+    }
+
+    #[test]
+    fn pin_arc_read_guard_hash() {
+        use std::collections::HashSet;
+
+        let a = PinArc::new(String::from("a"));
+        let b = PinArc::new(String::from("b"));
+        let present = PinArc::new(String::from("b"));
+        let absent = PinArc::new(String::from("c"));
+        let set: HashSet<_> = vec![a.read().unwrap(), b.read().unwrap()].into_iter().collect();
+
+        assert!(set.contains(&present.read().unwrap()));
+        assert!(!set.contains(&absent.read().unwrap()));
+    }
+
+    #[test]
+    fn pin_rc_ref_hash() {
+        use std::collections::HashSet;
+
+        let a = PinRc::new(1);
+        let b = PinRc::new(2);
+        let present = PinRc::new(2);
+        let absent = PinRc::new(3);
+        let set: HashSet<_> = vec![a.borrow(), b.borrow()].into_iter().collect();
+
+        assert!(set.contains(&present.borrow()));
+        assert!(!set.contains(&absent.borrow()));
+    }
+
+    #[test]
+    fn pin_arc_owned_guards() {
+        use std::thread;
+
+        let arc = PinArc::new(5);
+        let read = arc.read_owned().unwrap();
+        thread::spawn(move || drop(arc)).join().unwrap();
+        assert_eq!(*read, 5);
+        drop(read);
+
+        fn lock(arc: PinArc<i32>) -> OwnedPinWriteGuard<i32> {
+            arc.write_owned().unwrap()
+        }
+        let arc = PinArc::new(5);
+        let mut write = lock(arc.clone());
+        *write.as_pin() += 1;
+        assert!(arc.try_read().is_err());
+        drop(write);
+        assert_eq!(*arc.read().unwrap(), 6);
+    }
+
+    #[test]
+    fn pin_arc_move_into() {
+        let src = PinArc::new(vec![1, 2, 3]);
+        let dst = PinArc::new(vec![4]);
+
+        PinArc::move_into(&src, &dst).unwrap();
+        assert_eq!(*src.read().unwrap(), vec![]);
+        assert_eq!(*dst.read().unwrap(), vec![1, 2, 3]);
+
+        PinArc::move_into(&dst, &dst).unwrap();
+        assert_eq!(*dst.read().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn pin_thread_local_arc() {
+        use std::sync::Arc;
+        use std::thread;
+
+        fn count() -> impl Generator<Yield = i32, Return = ()> {
+            || {
+                for i in 0..10 {
+                    yield i;
+                }
+            }
+        }
+
+        fn drive<G>(local: &PinThreadLocalArc<G>) -> (PinArc<G>, Vec<i32>)
+            where G: Generator<Yield = i32, Return = ()>
+        {
+            let gen = local.get();
+            let mut results = Vec::new();
+            while let GeneratorState::Yielded(x) = gen.write().unwrap().as_pin().resume() {
+                results.push(x);
+            }
+            assert!(PinArc::ptr_eq(&gen, &local.get()));
+            (gen, results)
+        }
+
+        let local = Arc::new(PinThreadLocalArc::new(count));
+        let other = {
+            let local = local.clone();
+            thread::spawn(move || drive(&local)).join().unwrap()
+        };
+        let mine = drive(&local);
+
+        assert!(!PinArc::ptr_eq(&mine.0, &other.0));
+        assert_eq!((0..10).collect::<Vec<_>>(), mine.1);
+        assert_eq!((0..10).collect::<Vec<_>>(), other.1);
+    }
+
+    #[test]
+    fn pin_rc_project() {
+        struct Task {
+            gen: Box<Generator<Yield = i32, Return = ()>>,
+            resumed: u32,
+        }
+
+        pin_rc_project! {
+            Task => #[unsafe_pinned] gen: Box<Generator<Yield = i32, Return = ()>>, resumed: u32
+        }
+
+        let task = PinRc::new(Task {
+            gen: Box::new(|| {
+                for i in 0..10 {
+                    yield i;
+                }
+            }),
+            resumed: 0,
+        });
+
+        let mut results = Vec::new();
+        loop {
+            let mut borrow = task.borrow_mut();
+            *Task::resumed(&mut borrow) += 1;
+            match Task::gen(borrow).resume() {
+                GeneratorState::Yielded(x) => results.push(x),
+                GeneratorState::Complete(()) => break,
+            }
+        }
+
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+        assert_eq!(task.borrow().resumed, 11);
+    }
+
+    #[test]
+    fn pin_rc_new_uninit() {
+        let rc = PinRc::<String>::new_uninit();
+        {
+            let mut borrow = rc.borrow_mut();
+            let ptr = MaybeUninit::pinned_ptr(&mut borrow.as_pin());
+            unsafe { ::std::ptr::write(ptr, "initialized".to_string()) };
+        }
+        let rc = unsafe { PinRc::assume_init(rc) };
+
+        assert_eq!(*rc.borrow(), "initialized");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "PinArc write lock held for")]
+    fn pin_arc_write_with_max_hold() {
+        use std::time::Duration;
+
+        let arc = PinArc::new(0);
+        {
+            let mut guard = arc.write_with_max_hold(Duration::from_secs(60)).unwrap();
+            *guard.as_pin() += 1;
+        }
+
+        let mut guard = arc.write_with_max_hold(Duration::from_millis(1)).unwrap();
+        *guard.as_pin() += 1;
+        ::std::thread::sleep(Duration::from_millis(20));
+    }
+
+    #[test]
+    fn pin_deref_mut_unpin() {
+        #[derive(Debug, PartialEq)]
+        struct Config {
+            retries: u32,
+            name: String,
+        }
+
+        let rc = PinRc::new(Config { retries: 1, name: "rc".into() });
+        {
+            let mut borrow = rc.borrow_mut();
+            borrow.retries += 1;
+            *borrow = Config { retries: borrow.retries * 10, name: "replaced".into() };
+        }
+        assert_eq!(*rc.borrow(), Config { retries: 20, name: "replaced".into() });
+
+        let arc = PinArc::new(5);
+        *arc.write().unwrap() = 6;
+        *arc.write().unwrap() += 1;
+        assert_eq!(*arc.read().unwrap(), 7);
+    }
+
+    #[test]
+    fn pin_arc_tick_all() {
+        fn countdown(from: u32) -> impl Generator<Yield = u32, Return = ()> {
+            move || {
+                for i in (1..from + 1).rev() {
+                    yield i;
+                }
+            }
+        }
+
+        fn letters() -> impl Generator<Yield = u32, Return = ()> {
+            static || {
+                let word = String::from("ab");
+                for c in word.chars() {
+                    yield c as u32;
+                }
+            }
+        }
+
+        let gens: Vec<Option<Box<Generator<Yield = u32, Return = ()>>>> = vec![
+            Some(Box::new(countdown(3))),
+            Some(Box::new(letters())),
+        ];
+        let arc = PinArc::new(gens);
+
+        assert_eq!(arc.tick_all(), vec![Some(3), Some('a' as u32)]);
+        assert_eq!(arc.tick_all(), vec![Some(2), Some('b' as u32)]);
+        assert_eq!(arc.tick_all(), vec![Some(1), None]);
+        assert_eq!(arc.tick_all(), vec![None, None]);
+        assert!(arc.read().unwrap().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn pin_rc_debug_counts() {
+        let rc = PinRc::new(1);
+        assert_eq!(PinRc::debug_counts(&rc), (1, 0));
+        let rc2 = rc.clone();
+        let weak = PinRc::downgrade(&rc);
+        assert_eq!(PinRc::debug_counts(&rc), (2, 1));
+        drop(rc2);
+        drop(weak);
+        assert_eq!(PinRc::debug_counts(&rc), (1, 0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "PinRc is not unique: 2 strong and 0 weak references")]
+    fn pin_rc_assert_unique() {
+        let rc = PinRc::new(1);
+        PinRc::assert_unique(&rc);
+        let _rc2 = rc.clone();
+        PinRc::assert_unique(&rc);
+    }
+
+    #[test]
+    fn pin_rc_into_arc_send() {
+        fn assert_send<T: Send>(value: T) -> T {
+            value
+        }
+
+        let rc = PinRc::new(5u32);
+        let weak = PinRc::downgrade(&rc);
+        let arc = assert_send(PinRc::into_arc(rc).unwrap());
+        assert!(weak.upgrade().is_none());
+
+        let handle = ::std::thread::spawn(move || {
+            let value = *arc.read().unwrap();
+            value + 1
+        });
+        assert_eq!(handle.join().unwrap(), 6);
+    }
+
+    #[test]
+    fn pin_arc_with_read() {
+        struct SelfRef {
+            value: u32,
+            ptr: *const u32,
+        }
+
+        fn check(this: &SelfRef) -> bool {
+            this.ptr == &this.value as *const u32
+        }
+
+        let arc = PinArc::new(SelfRef { value: 3, ptr: ::std::ptr::null() });
+        {
+            let mut guard = arc.write().unwrap();
+            let mut pin = guard.as_pin();
+            let this = unsafe { Pin::get_mut(&mut pin) };
+            this.ptr = &this.value;
+        }
+
+        assert_eq!(arc.with_read(check).ok(), Some(true));
+        assert_eq!(arc.with_read(|this| unsafe { *this.ptr }).ok(), Some(3));
+    }
+
+    #[test]
+    fn pin_arc_write_guard_map_split() {
+        struct Driver<G> {
+            gen: G,
+            limit: u32,
+        }
+
+        fn run<G: Generator<Yield = u32, Return = ()>>(arc: &PinArc<Driver<G>>) -> Vec<u32> {
+            let mut guard = arc.write().unwrap();
+            let (mut gen, limit) = unsafe {
+                PinRwLockWriteGuard::map_split(&mut guard, |d| (&mut d.gen, &d.limit))
+            };
+            let mut results = Vec::new();
+            while let GeneratorState::Yielded(x) = gen.resume() {
+                if x > *limit {
+                    break;
+                }
+                results.push(x);
+            }
+            results
+        }
+
+        let arc = PinArc::new(Driver {
+            gen: || {
+                for i in 0.. {
+                    yield i;
+                }
+            },
+            limit: 4,
+        });
+
+        assert_eq!(run(&arc), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn pin_from_pin_box() {
+        let rc = PinRc::from_pin_box(PinBox::new(vec![1, 2]));
+        rc.borrow_mut().push(3);
+        assert_eq!(*rc.borrow(), vec![1, 2, 3]);
+
+        let arc = PinArc::from_pin_box(PinBox::new(String::from("boxed")));
+        assert_eq!(*arc.read().unwrap(), "boxed");
+
+        let rc = PinRc::from_std_pin_box(::std::boxed::PinBox::new(5));
+        assert_eq!(*rc.borrow(), 5);
+        let arc = PinArc::from_std_pin_box(::std::boxed::PinBox::new(6));
+        assert_eq!(*arc.read().unwrap(), 6);
+    }
+
+    #[test]
+    fn pin_arc_par_tick() {
+        fn steps(id: u32, count: u32) -> impl Generator<Yield = (u32, u32), Return = ()> + Send {
+            move || {
+                for i in 0..count {
+                    yield (id, i);
+                }
+            }
+        }
+
+        let arc = PinArc::new((0..4).map(|id| Some(steps(id, id))).collect::<Vec<_>>());
+
+        assert_eq!(arc.par_tick(2), vec![None, Some((1, 0)), Some((2, 0)), Some((3, 0))]);
+        assert_eq!(arc.par_tick(3), vec![None, None, Some((2, 1)), Some((3, 1))]);
+        assert_eq!(arc.par_tick(1), vec![None, None, None, Some((3, 2))]);
+        assert_eq!(arc.par_tick(8), vec![None, None, None, None]);
+    }
+
+    #[test]
+    fn pin_borrow_map() {
+        struct Inner {
+            gen: Option<Box<Generator<Yield = u32, Return = ()> + Send + Sync>>,
+            label: &'static str,
+        }
+
+        struct Outer {
+            inner: Inner,
+        }
+
+        fn project_gen(mut outer: Pin<Outer>) -> Pin<Box<Generator<Yield = u32, Return = ()> + Send + Sync>> {
+            unsafe {
+                let outer = Pin::get_mut(&mut outer) as *mut Outer;
+                Pin::new_unchecked((*outer).inner.gen.as_mut().unwrap())
+            }
+        }
+
+        fn gen() -> Option<Box<Generator<Yield = u32, Return = ()> + Send + Sync>> {
+            Some(Box::new(|| {
+                yield 1;
+                yield 2;
+            }))
+        }
+
+        let rc = PinRc::new(Outer { inner: Inner { gen: gen(), label: "rc" } });
+        assert_eq!(*rc.borrow_map(|outer| &outer.inner.label), "rc");
+        match rc.borrow_mut_map(project_gen).resume() {
+            GeneratorState::Yielded(x) => assert_eq!(x, 1),
+            GeneratorState::Complete(()) => panic!("generator completed early"),
+        }
+
+        let arc = PinArc::new(Outer { inner: Inner { gen: gen(), label: "arc" } });
+        assert_eq!(*arc.read_map(|outer| &outer.inner.label).unwrap(), "arc");
+        {
+            let mut guard = arc.write_map(project_gen).unwrap();
+            match guard.as_pin().resume() {
+                GeneratorState::Yielded(x) => assert_eq!(x, 1),
+                GeneratorState::Complete(()) => panic!("generator completed early"),
+            }
+        }
+        assert!(arc.try_write().is_ok());
+    }
+
+    #[test]
+    fn pin_arc_write_guard_pin_send() {
+        fn assert_send<T: Send>() {}
+
+        fn count() -> impl Generator<Yield = u32, Return = ()> + Send + Sync {
+            || {
+                yield 1;
+                yield 2;
+            }
+        }
+
+        fn pin_of<'a, G: Send + 'a>(_: &PinRwLockWriteGuard<'a, G>) {
+            assert_send::<Pin<'a, G>>();
+        }
+
+        let arc = PinArc::new(count());
+        pin_of(&arc.write().unwrap());
+
+        let handle = arc.clone();
+        let first = ::std::thread::spawn(move || {
+            let mut guard = handle.write().unwrap();
+            guard.resume()
+        });
+        match first.join().unwrap() {
+            GeneratorState::Yielded(x) => assert_eq!(x, 1),
+            GeneratorState::Complete(()) => panic!("generator completed early"),
+        }
+        let mut guard = arc.write().unwrap();
+        match guard.resume() {
+            GeneratorState::Yielded(x) => assert_eq!(x, 2),
+            GeneratorState::Complete(()) => panic!("generator completed early"),
+        }
+    }
+
+    #[test]
+    fn pin_rc_slice_iter() {
+        fn count(from: u32) -> impl Generator<Yield = u32, Return = ()> {
+            move || {
+                yield from;
+                yield from + 1;
+            }
+        }
+
+        let rc: PinRc<[_]> = PinRc::new([count(0), count(10), count(20)]);
+        let mut results = Vec::new();
+        for mut gen in &mut rc.borrow_mut() {
+            if let GeneratorState::Yielded(x) = gen.resume() {
+                results.push(x);
+            }
+        }
+        assert_eq!(results, vec![0, 10, 20]);
+
+        let rc: PinRc<[u32]> = PinRc::new([1, 2, 3]);
+        let sum: u32 = (&rc.borrow()).into_iter().sum();
+        assert_eq!(sum, 6);
+    }
+
+    #[test]
+    fn weak_value_map_arc() {
+        let mut map = ArcWeakValueMap::new();
+        let a = PinArc::new(String::from("a"));
+        let b = PinArc::new(String::from("b"));
+        map.insert(1, &a);
+        map.insert(2, &b);
+
+        assert!(PinArc::ptr_eq(&map.get(&1).unwrap(), &a));
+        drop(b);
+        assert_eq!(map.len(), 2);
+        assert!(map.get(&2).is_none());
+        assert_eq!(map.len(), 1);
+        assert!(map.get(&3).is_none());
+    }
+
+    #[test]
+    fn weak_value_map_rc_retain_live() {
+        let mut map = RcWeakValueMap::new();
+        let values: Vec<_> = (0..4).map(PinRc::new).collect();
+        for (i, value) in values.iter().enumerate() {
+            map.insert(i, value);
+        }
+
+        let kept: Vec<_> = values.into_iter().filter(|v| *v.borrow() % 2 == 0).collect();
+        assert_eq!(map.len(), 4);
+        map.retain_live();
+        assert_eq!(map.len(), 2);
+        let two = map.get(&2).unwrap();
+        assert!(PinRc::ptr_eq(&two, &kept[1]));
+    }
+
+    #[test]
+    fn pin_arc_id() {
+        use std::collections::HashMap;
+
+        let a = PinArc::new(1u64);
+        let b = PinArc::new(2u64);
+        let mut results = HashMap::new();
+        results.insert(a.id(), "a");
+        results.insert(b.id(), "b");
+        assert_eq!(results[&a.clone().id()], "a");
+        assert!(b.has_id(&b.id()));
+        assert!(!b.has_id(&a.id()));
+
+        // Freeing the value doesn't let a new allocation take over its id
+        let stale = a.id();
+        drop(a);
+        let later: Vec<_> = (0..100).map(PinArc::new).collect();
+        assert!(later.iter().all(|arc| !arc.has_id(&stale) && arc.id() != stale));
+    }
+
+    #[test]
+    fn pin_deep_clone() {
+        let rc = PinRc::new(vec![1]);
+        let rc2 = PinRc::deep_clone(&rc);
+        assert!(!PinRc::ptr_eq(&rc, &rc2));
+        rc2.borrow_mut().push(2);
+        assert_eq!(*rc.borrow(), vec![1]);
+        assert_eq!(*rc2.borrow(), vec![1, 2]);
+
+        let arc = PinArc::new(vec![1]);
+        let arc2 = PinArc::deep_clone(&arc);
+        assert!(!PinArc::ptr_eq(&arc, &arc2));
+        arc.write().unwrap().push(3);
+        assert_eq!(*arc.read().unwrap(), vec![1, 3]);
+        assert_eq!(*arc2.read().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn pin_rc_borrow_unchecked() {
+        let rcs: Vec<_> = (0..10).map(PinRc::new).collect();
+        let mut sum = 0;
+        for rc in &rcs {
+            // Nothing is mutably borrowed during the loop
+            sum += *unsafe { rc.borrow_unchecked() };
+            assert!(rc.try_borrow_mut().is_ok());
+        }
+        assert_eq!(sum, 45);
+    }
+
+    #[test]
+    fn pin_replace_and_swap() {
+        let a = PinRc::new(String::from("a"));
+        let b = PinRc::new(String::from("b"));
+        let a2 = a.clone();
+        assert_eq!(a.replace(String::from("c")), "a");
+        a.swap(&b);
+        a.swap(&a2);
+        assert_eq!(*a.borrow(), "b");
+        assert_eq!(*b.borrow(), "c");
+        assert_eq!(PinRc::debug_counts(&a), (2, 0));
+        assert_eq!(PinRc::debug_counts(&b), (1, 0));
+
+        let x = PinArc::new(1);
+        let y = PinArc::new(2);
+        assert_eq!(x.replace(3).ok(), Some(1));
+        assert!(x.swap(&y).is_ok());
+        assert!(x.swap(&x).is_ok());
+        assert_eq!(*x.read().unwrap(), 2);
+        assert_eq!(*y.read().unwrap(), 3);
+        assert_eq!(PinArc::strong_count(&x), 1);
+    }
+
+    #[test]
+    fn pin_arc_modify() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let mut arc = PinArc::new(0);
+        for _ in 0..3 {
+            arc.modify(|mut value| *value += 1);
+        }
+        assert_eq!(*arc.read().unwrap(), 3);
+
+        // A unique handle doesn't need the lock, so modifying it succeeds
+        // even while the lock is held. The allocation is leaked afterwards,
+        // so that the lock is never destroyed while still locked.
+        {
+            let mut unique = PinArc::new(0);
+            ::std::mem::forget(unique.write().unwrap());
+            assert!(unique.try_read().is_err());
+            assert_eq!(unique.modify(|mut value| { *value += 1; *value }), 1);
+            assert_eq!(PinArc::leak(unique).ok(), Some(&1));
+        }
+
+        // Once shared, modifications wait for other writers
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let arc2 = arc.clone();
+        let handle = thread::spawn(move || {
+            let mut guard = arc2.write().unwrap();
+            locked_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            *guard.as_pin() *= 10;
+        });
+        locked_rx.recv().unwrap();
+        release_tx.send(()).unwrap();
+        assert_eq!(arc.modify(|mut value| { *value += 1; *value }), 31);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn pin_rc_try_with_mut() {
+        let rc = PinRc::new(0);
+        let result = rc.try_with_mut(|mut outer| {
+            *outer += 1;
+            assert!(rc.try_with(|_| panic!("closure called")).is_err());
+            rc.try_with_mut(|_| panic!("closure called")).is_err()
+        });
+        assert_eq!(result.ok(), Some(true));
+        assert_eq!(rc.try_with(|value| *value).ok(), Some(1));
+    }
+
+    #[test]
+    fn pin_arc_wait_write() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let arc = PinArc::new(0);
+        let condvar = Arc::new(PinCondvar::new());
+
+        let (arc2, condvar2) = (arc.clone(), condvar.clone());
+        let handle = thread::spawn(move || {
+            for _ in 0..5 {
+                *arc2.write().unwrap() += 1;
+                condvar2.notify_all();
+            }
+        });
+
+        let mut guard = arc.wait_write(&condvar, |count| *count == 5).unwrap();
+        *guard = 0;
+        drop(guard);
+        handle.join().unwrap();
+        assert_eq!(*arc.read().unwrap(), 0);
+    }
+
+    #[test]
+    fn pin_weak_ptr_eq() {
+        let arc = PinArc::new(1);
+        let arc2 = arc.clone();
+        let other = PinArc::new(1);
+        let weak = PinArc::downgrade(&arc);
+        assert!(PinArc::weak_ptr_eq(&arc, &weak));
+        assert!(PinArc::weak_ptr_eq(&arc2, &weak));
+        assert!(!PinArc::weak_ptr_eq(&other, &weak));
+        assert!(!PinArc::weak_ptr_eq(&arc, &Default::default()));
+        assert_eq!(PinArc::strong_count(&arc), 2);
+
+        let rc = PinRc::new(1);
+        let weak = PinRc::downgrade(&rc);
+        assert!(PinRc::weak_ptr_eq(&rc.clone(), &weak));
+        assert!(!PinRc::weak_ptr_eq(&PinRc::new(1), &weak));
+        assert!(!PinRc::weak_ptr_eq(&rc, &Default::default()));
+    }
+
+    #[test]
+    fn pin_arc_value_eq() {
+        let a = PinArc::new(String::from("x"));
+        let b = PinArc::new(String::from("x"));
+        let c = PinArc::new(String::from("y"));
+        assert_eq!(PinArc::value_eq(&a, &b).ok(), Some(true));
+        assert_eq!(PinArc::value_eq(&b, &a).ok(), Some(true));
+        assert_eq!(PinArc::value_eq(&a, &c).ok(), Some(false));
+
+        // The same allocation is equal without locking it
+        let _guard = a.write().unwrap();
+        assert_eq!(PinArc::value_eq(&a, &a.clone()).ok(), Some(true));
+    }
+
+    #[test]
+    fn pin_leak() {
+        fn global() -> &'static String {
+            static INIT: ::std::sync::Once = ::std::sync::ONCE_INIT;
+            static mut GLOBAL: Option<&'static String> = None;
+            unsafe {
+                INIT.call_once(|| GLOBAL = PinArc::leak(PinArc::new(String::from("global"))).ok());
+                GLOBAL.unwrap()
+            }
+        }
+
+        assert_eq!(global(), "global");
+        assert!(::std::ptr::eq(global(), global()));
+
+        let arc = PinArc::new(1);
+        let other = arc.clone();
+        let arc = PinArc::leak(arc).err().unwrap();
+        drop(other);
+        assert_eq!(*PinArc::leak(arc).ok().unwrap(), 1);
+
+        let rc = PinRc::new(vec![1, 2]);
+        let rc2 = rc.clone();
+        let leaked = PinRc::leak(rc);
+        assert_eq!(*leaked, vec![1, 2]);
+        assert!(rc2.try_borrow_mut().is_err());
+        assert_eq!(*rc2.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn pin_ref_inspect() {
+        struct Task<G> {
+            gen: G,
+            name: &'static str,
+        }
+
+        fn gen_of<G>(mut task: Pin<Task<G>>) -> Pin<G> {
+            unsafe {
+                let task = Pin::get_mut(&mut task) as *mut Task<G>;
+                Pin::new_unchecked(&mut (*task).gen)
+            }
+        }
+
+        let rc = PinRc::new(Task {
+            gen: || {
+                yield 1;
+                yield 2;
+            },
+            name: "task",
+        });
+
+        let mut log = Vec::new();
+        let name = PinRef::inspect(rc.borrow(), |task| log.push(task.name));
+        assert_eq!(name.name, "task");
+        drop(name);
+
+        for _ in 0..2 {
+            let borrow = PinRefMut::inspect(rc.borrow_mut(), |task| log.push(task.name));
+            let mut gen = PinRefMut::map(borrow, gen_of);
+            if let GeneratorState::Yielded(x) = gen.resume() {
+                log.push(if x == 1 { "one" } else { "two" });
+            }
+        }
+        assert_eq!(log, vec!["task", "task", "one", "task", "two"]);
+    }
+
+    #[test]
+    fn pin_inner() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use std::sync::{Arc, RwLock};
+
+        fn arc_total(arc: &Arc<RwLock<Vec<u32>>>) -> u32 {
+            arc.read().unwrap().iter().sum()
+        }
+
+        fn rc_push(rc: &Rc<RefCell<Vec<u32>>>) -> usize {
+            rc.borrow_mut().push(3);
+            Rc::strong_count(rc)
+        }
+
+        let arc = PinArc::new(vec![1, 2]);
+        let _arc2 = arc.clone();
+        assert_eq!(arc_total(arc.inner()), 3);
+        assert_eq!(Arc::strong_count(arc.inner()), 2);
+
+        let rc = PinRc::new(vec![1, 2]);
+        assert_eq!(rc_push(rc.inner()), 1);
+        assert_eq!(*rc.borrow(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn pin_arc_swap_concurrent() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let swap = Arc::new(PinArcSwap::new(PinArc::new(0)));
+        let handles: Vec<_> = (1..5).map(|id| {
+            let swap = swap.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    let old = swap.swap(PinArc::new(id * 1000 + i));
+                    let loaded = swap.load();
+                    assert!(*old.read().unwrap() < 5000);
+                    assert!(*loaded.read().unwrap() < 5000);
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let current = swap.load();
+        assert_eq!(PinArc::strong_count(&current), 2);
+        let stale = PinArc::new(-1);
+        assert!(swap.compare_and_swap(&stale, PinArc::new(-2)).is_err());
+        let old = swap.compare_and_swap(&current, PinArc::new(-3)).ok().unwrap();
+        assert!(PinArc::ptr_eq(&old, &current));
+        let new = swap.load();
+        assert_eq!(*new.read().unwrap(), -3);
+    }
+
+    #[test]
+    fn pin_arc_with_drop_hook() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::new(AtomicUsize::new(0));
+        let (calls2, seen2) = (calls.clone(), seen.clone());
+        let arc = PinArc::with_drop_hook(5, move |value| {
+            calls2.fetch_add(1, Ordering::SeqCst);
+            seen2.store(*value, Ordering::SeqCst);
+        });
+
+        {
+            let mut guard = arc.write().unwrap();
+            *DropHook::as_pin(guard.as_pin()) += 1;
+        }
+        let clones: Vec<_> = (0..3).map(|_| arc.clone()).collect();
+        assert_eq!(**arc.read().unwrap(), 6);
+        drop(arc);
+        drop(clones);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(seen.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn pin_arc_write_guard_try_map() {
+        enum State {
+            Running(u32),
+            Done(String),
+        }
+
+        fn running(mut state: Pin<State>) -> Result<Pin<u32>, &'static str> {
+            unsafe {
+                match *(Pin::get_mut(&mut state) as *mut State) {
+                    State::Running(ref mut n) => Ok(Pin::new_unchecked(n)),
+                    State::Done(_) => Err("done"),
+                }
+            }
+        }
+
+        fn done(mut state: Pin<State>) -> Result<Pin<String>, &'static str> {
+            unsafe {
+                match *(Pin::get_mut(&mut state) as *mut State) {
+                    State::Done(ref mut s) => Ok(Pin::new_unchecked(s)),
+                    State::Running(_) => Err("running"),
+                }
+            }
+        }
+
+        let arc = PinArc::new(State::Running(1));
+        {
+            let mut n = PinRwLockWriteGuard::try_map(arc.write().unwrap(), running).ok().unwrap();
+            *n.as_pin() += 1;
+            assert_eq!(*n, 2);
+        }
+
+        *arc.write().unwrap() = State::Done(String::from("finished"));
+        let guard = arc.write().unwrap();
+        let (guard, e) = PinRwLockWriteGuard::try_map(guard, running).err().unwrap();
+        assert_eq!(e, "done");
+        let s = PinRwLockWriteGuard::try_map(guard, done).ok().unwrap();
+        assert_eq!(&*s, "finished");
+    }
+
+    #[test]
+    fn pin_rc_unpin_unique() {
+        let rc = PinRc::new(String::from("unique"));
+        let inner = unsafe { PinRc::unpin_unique(rc) }.ok().unwrap();
+        assert_eq!(::std::rc::Rc::try_unwrap(inner).ok().unwrap().into_inner(), "unique");
+
+        let rc = PinRc::new(String::from("shared"));
+        let rc2 = rc.clone();
+        let rc = unsafe { PinRc::unpin_unique(rc) }.err().unwrap();
+        drop(rc2);
+        let weak = PinRc::downgrade(&rc);
+        let rc = unsafe { PinRc::unpin_unique(rc) }.err().unwrap();
+        drop(weak);
+        assert!(unsafe { PinRc::unpin_unique(rc) }.is_ok());
+    }
+
+    #[test]
+    fn pin_arc_write_all() {
+        use std::thread;
+
+        let arcs: Vec<_> = (0..4).map(PinArc::new).collect();
+        {
+            let dup = vec![arcs[2].clone(), arcs[0].clone(), arcs[2].clone()];
+            let guards = write_all(&dup).unwrap();
+            let values: Vec<_> = guards.iter().map(|g| g.as_ref().map(|g| **g)).collect();
+            assert_eq!(values, vec![Some(2), Some(0), None]);
+        }
+
+        let forward: Vec<_> = arcs[..3].to_vec();
+        let backward: Vec<_> = arcs[1..].iter().rev().cloned().collect();
+        let handles: Vec<_> = vec![forward, backward].into_iter().map(|set| {
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    for guard in write_all(&set).unwrap() {
+                        *guard.unwrap() += 1;
+                    }
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let values: Vec<_> = arcs.iter().map(|arc| *arc.read().unwrap()).collect();
+        assert_eq!(values, vec![1000, 2001, 2002, 1003]);
+    }
+
+    #[test]
+    fn pin_weak_boxed_raw() {
+        let arc = PinArc::new(4);
+        let ptr = ::pin_arc::PinWeak::into_boxed_raw(PinArc::downgrade(&arc));
+        assert_eq!(PinArc::weak_count(&arc), 1);
+        let weak = unsafe { ::pin_arc::PinWeak::from_boxed_raw(ptr) };
+        assert!(PinArc::ptr_eq(&weak.upgrade().unwrap(), &arc));
+
+        let ptr = ::pin_arc::PinWeak::into_boxed_raw(::pin_arc::PinWeak::<i32>::default());
+        let weak = unsafe { ::pin_arc::PinWeak::from_boxed_raw(ptr) };
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn pin_cow() {
+        fn lookup<'a>(cache: &'a [String], shared: &PinRc<String>, i: usize) -> PinCow<'a, String> {
+            match cache.get(i) {
+                Some(value) => PinCow::Borrowed(value),
+                None => PinCow::Owned(shared.clone()),
+            }
+        }
+
+        let cache = vec![String::from("cached")];
+        let shared = PinRc::new(String::from("shared"));
+
+        let borrowed = lookup(&cache, &shared, 0);
+        assert!(!borrowed.is_owned());
+        assert_eq!(borrowed.with(|s| s.len()), 6);
+        let cloned = borrowed.into_owned();
+        assert_eq!(*cloned.borrow(), "cached");
+        assert_eq!(PinRc::strong_count(&cloned), 1);
+
+        let owned = lookup(&cache, &shared, 1);
+        assert!(owned.is_owned());
+        assert_eq!(owned.with(|s| s.clone()), "shared");
+        assert!(PinRc::ptr_eq(&owned.into_owned(), &shared));
+    }
+
+    #[test]
+    fn pin_arc_spin_lock() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let x = PinArc::new(0);
+        *x.spin_write(0).as_pin() += 1;
+        assert_eq!(*x.spin_read(10), 1);
+
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let y = x.clone();
+        let holder = thread::spawn(move || {
+            let mut guard = y.write().unwrap();
+            locked_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(20));
+            *guard.as_pin() += 1;
+        });
+        locked_rx.recv().unwrap();
+        // Too few spins to outlast the holder, so this falls back to blocking
+        *x.spin_write(1).as_pin() += 1;
+        holder.join().unwrap();
+        assert_eq!(*x.spin_read(1), 3);
+    }
+
+    #[test]
+    fn clone_from() {
+        let a = PinRc::new(1);
+        let mut b = a.clone();
+        // Cloning from the same allocation leaves the count unchanged
+        assert_eq!(PinRc::strong_count(&a), 2);
+        b.clone_from(&a);
+        assert_eq!(PinRc::strong_count(&a), 2);
+        let mut c = PinRc::new(2);
+        c.clone_from(&a);
+        assert!(PinRc::ptr_eq(&c, &a));
+        assert_eq!(PinRc::strong_count(&a), 3);
+
+        let x = PinArc::new(1);
+        let mut y = x.clone();
+        // Cloning from the same allocation leaves the count unchanged
+        assert_eq!(PinArc::strong_count(&x), 2);
+        y.clone_from(&x);
+        assert_eq!(PinArc::strong_count(&x), 2);
+        let mut z = PinArc::new(2);
+        z.clone_from(&x);
+        assert!(PinArc::ptr_eq(&z, &x));
+        assert_eq!(PinArc::strong_count(&x), 3);
+    }
+
+    #[test]
+    fn reborrow() {
+        fn step<G: Generator<Yield = i32, Return = ()>>(mut gen: Pin<G>) -> Option<i32> {
+            match gen.resume() {
+                GeneratorState::Yielded(value) => Some(value),
+                GeneratorState::Complete(()) => None,
+            }
+        }
+
+        let x = PinRc::new(|| { yield 1; yield 2; });
+        let mut guard = x.borrow_mut();
+        assert_eq!(step(guard.reborrow()), Some(1));
+        assert_eq!(step(guard.reborrow()), Some(2));
+        assert_eq!(step(guard.reborrow()), None);
+
+        let y = PinArc::new(5);
+        let mut guard = y.write().unwrap();
+        *guard.reborrow() += 1;
+        *guard.reborrow() += 1;
+        assert_eq!(*guard.reborrow_shared(), 7);
+    }
+
+    #[test]
+    fn into_std_guards() {
+        use std::cell::{Ref, RefMut};
+
+        fn total(values: Ref<Vec<i32>>) -> i32 {
+            values.iter().sum()
+        }
+        fn push(mut values: RefMut<Vec<i32>>, value: i32) {
+            values.push(value);
+        }
+
+        let x = PinRc::new(vec![1, 2]);
+        push(PinRefMut::into_ref_mut(x.borrow_mut()), 3);
+        assert_eq!(total(PinRef::into_ref(x.borrow())), 6);
+    }
+
+    #[test]
+    fn by_ptr_and_by_value() {
+        use std::collections::HashMap;
+
+        let a = PinRc::new(1);
+        let b = PinRc::new(1);
+        let mut by_ptr = HashMap::new();
+        let mut by_value = HashMap::new();
+        for handle in &[a.clone(), b.clone(), a.clone()] {
+            *by_ptr.entry(ByPtr(handle.clone())).or_insert(0) += 1;
+            *by_value.entry(ByValue(handle.clone())).or_insert(0) += 1;
+        }
+        assert_eq!(by_ptr.len(), 2);
+        assert_eq!(by_ptr[&ByPtr(b.clone())], 1);
+        assert_eq!(by_value.len(), 1);
+        assert_eq!(by_value[&ByValue(b)], 3);
+
+        let x = PinArc::new(String::from("x"));
+        let y = PinArc::new(String::from("x"));
+        assert!(ByPtr(x.clone()) != ByPtr(y.clone()));
+        assert!(ByValue(x.clone()) == ByValue(y.clone()));
+        assert!(ByValue(x.clone()) < ByValue(PinArc::new(String::from("y"))));
+        // Comparing a handle with itself doesn't take the lock twice
+        let _guard = x.read().unwrap();
+        assert!(ByValue(x.clone()) == ByValue(x.clone()));
+    }
+
+    #[test]
+    fn pin_box_std_conversions() {
+        let gen = ::std::boxed::PinBox::new(static || {
+            let x = 1i32;
+            let r = &x;
+            yield *r;
+            yield *r + 1;
+        });
+        let mut gen = PinBox::from_std(gen);
+        assert_eq!(gen.as_pin().resume(), GeneratorState::Yielded(1));
+        // Converting back keeps the generator in the same allocation
+        let mut gen = PinBox::into_std(gen);
+        assert_eq!(gen.as_pin().resume(), GeneratorState::Yielded(2));
     }
 }
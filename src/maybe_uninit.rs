@@ -0,0 +1,50 @@
+use std::mem::{ManuallyDrop, Pin};
+
+/// Storage for a value which may not have been initialized yet.
+///
+/// This has the same size and alignment as `T`, and is never dropped: an
+/// initialized value must be read out or converted with `assume_init`
+/// (for example `PinArc::assume_init`) before it will be dropped.
+#[allow(dead_code)]
+pub union MaybeUninit<T> {
+    uninit: (),
+    value: ManuallyDrop<T>,
+}
+
+impl<T> MaybeUninit<T> {
+    /// Create storage holding no value.
+    #[inline]
+    pub fn uninitialized() -> MaybeUninit<T> {
+        MaybeUninit { uninit: () }
+    }
+
+    /// Create storage holding an initialized value.
+    #[inline]
+    pub fn new(value: T) -> MaybeUninit<T> {
+        MaybeUninit { value: ManuallyDrop::new(value) }
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const T {
+        self as *const MaybeUninit<T> as *const T
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self as *mut MaybeUninit<T> as *mut T
+    }
+
+    /// Get a pointer to the storage behind a pinned reference.
+    ///
+    /// This can be used to initialize a value in place, once it is already
+    /// pinned. Writing through the pointer is unsafe, and the pointer must
+    /// not be used to move a value which has already been initialized.
+    ///
+    /// The pointer is derived from the pinned mutable reference, so it may
+    /// be written through for as long as that reference is valid.
+    #[inline]
+    pub fn pinned_ptr(this: &mut Pin<MaybeUninit<T>>) -> *mut T {
+        // Taking the address does not move the storage
+        unsafe { Pin::get_mut(this) as *mut MaybeUninit<T> as *mut T }
+    }
+}
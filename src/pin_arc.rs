@@ -2,12 +2,65 @@ use std::sync::{
     Arc, Weak, RwLock, RwLockReadGuard, RwLockWriteGuard, LockResult, PoisonError, TryLockError,
     TryLockResult
 };
-use std::mem::Pin;
-use std::marker::Unpin;
-use std::ops::Deref;
+use std::sync::atomic;
+#[cfg(debug_assertions)]
+use std::time::Instant;
+#[cfg(debug_assertions)]
+use std::thread;
+use std::time::Duration;
+use std::mem::{self, Pin};
+use std::ptr;
+use std::marker::{Unpin, Unsize};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut, CoerceUnsized};
+use std::borrow;
+use std::boxed;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 use std::fmt;
 
+use pin_rc::PinRc;
+use pin_box::PinBox;
+use maybe_uninit::MaybeUninit;
+use by_key::SharedHandle;
+
+/// Defines a function returning a process-wide map from allocation
+/// addresses to `$value`, which is created on first use and never freed.
+#[cfg(any(feature = "pin-tracking", feature = "leak-check"))]
+macro_rules! global_registry {
+    (fn $name:ident() -> $value:ty) => {
+        fn $name() -> &'static ::std::sync::Mutex<::std::collections::HashMap<usize, $value>> {
+            use std::sync::{Mutex, Once, ONCE_INIT};
+            use std::collections::HashMap;
+
+            static INIT: Once = ONCE_INIT;
+            static mut REGISTRY: *const Mutex<HashMap<usize, $value>> = 0 as *const _;
+            unsafe {
+                INIT.call_once(|| REGISTRY = Box::into_raw(Box::new(Mutex::new(HashMap::new()))));
+                &*REGISTRY
+            }
+        }
+    }
+}
+
+/// A thread-safe reference counted pointer to a pinned value in an `RwLock`.
+///
+/// As with `Arc<RwLock<T>>`, `PinArc<T>` is only `Send` and `Sync` when `T`
+/// is both `Send` and `Sync`:
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(pin_rc::PinArc::new(std::rc::Rc::new(())));
+/// ```
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(pin_rc::PinArc::new(std::cell::Cell::new(0)));
+/// ```
+///
+/// `PinArc<T>` has the same layout as `Arc<RwLock<T>>`.
 #[derive(Default, Debug)]
+#[repr(transparent)]
 pub struct PinArc<T: ?Sized> {
     inner: Arc<RwLock<T>>
 }
@@ -20,30 +73,361 @@ pub struct PinRwLockReadGuard<'a, T: ?Sized + 'a> {
     inner: RwLockReadGuard<'a, T>
 }
 
+/// Like `RwLockWriteGuard`, the guard itself is never `Send`, as the lock
+/// must be released by the thread which acquired it:
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>(_: T) {}
+/// let arc = pin_rc::PinArc::new(0);
+/// assert_send(arc.write().unwrap());
+/// ```
+///
+/// However, the `Pin` returned by `as_pin` is `Send` whenever `T` is, so
+/// the pinned value can be driven by another thread while the guard is
+/// held, provided that thread finishes before the guard is dropped.
 pub struct PinRwLockWriteGuard<'a, T: ?Sized + 'a> {
-    inner: RwLockWriteGuard<'a, T>
+    inner: RwLockWriteGuard<'a, T>,
+    token: PinToken,
+    hold: HoldTimer,
+}
+
+/// A read guard which keeps its own strong reference to the value, and so
+/// is not tied to the lifetime of the `PinArc` it came from.
+pub struct OwnedPinReadGuard<T: ?Sized + 'static> {
+    // Declared first so that the lock is released before the reference is dropped
+    inner: RwLockReadGuard<'static, T>,
+    _arc: Arc<RwLock<T>>,
+}
+
+/// A read guard for part of the locked value, returned by `PinArc::read_map`.
+///
+/// Like the unmapped guards, mapped guards are never `Send`, and can be
+/// shared between threads when both the locked value and the part of it
+/// they point to are `Sync`:
+///
+/// ```
+/// # use pin_rc::PinArc;
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// let arc = PinArc::new((1, 2));
+/// assert_sync(&arc.read_map(|pair| &pair.0).unwrap());
+/// ```
+///
+/// ```compile_fail
+/// # use pin_rc::PinArc;
+/// fn assert_send<T: Send>(_: T) {}
+/// let arc = PinArc::new((1, 2));
+/// assert_send(arc.read_map(|pair| &pair.0).unwrap());
+/// ```
+pub struct MappedPinRwLockReadGuard<'a, T: ?Sized + 'a, U: ?Sized + 'a> {
+    // Keeps the lock held for as long as `value` is in use
+    _guard: PinRwLockReadGuard<'a, T>,
+    value: *const U,
+    _marker: PhantomData<*mut ()>,
+}
+
+unsafe impl<'a, T: ?Sized + Sync, U: ?Sized + Sync> Sync for MappedPinRwLockReadGuard<'a, T, U> {}
+
+/// A write guard for part of the locked value, returned by
+/// `PinArc::write_map`.
+///
+/// ```compile_fail
+/// # #![feature(pin)]
+/// # use pin_rc::PinArc;
+/// use std::cell::Cell;
+/// use std::mem::Pin;
+///
+/// fn first<'a>(mut pair: Pin<'a, (Cell<i32>, i32)>) -> Pin<'a, Cell<i32>> {
+///     unsafe {
+///         let pair = Pin::get_mut(&mut pair) as *mut (Cell<i32>, i32);
+///         Pin::new_unchecked(&mut (*pair).0)
+///     }
+/// }
+/// fn assert_sync<T: Sync>(_: &T) {}
+///
+/// let arc = PinArc::new((Cell::new(1), 2));
+/// assert_sync(&arc.write_map(first).unwrap());
+/// ```
+pub struct MappedPinRwLockWriteGuard<'a, T: ?Sized + 'a, U: ?Sized + 'a> {
+    // Keeps the lock held for as long as `value` is in use
+    guard: PinRwLockWriteGuard<'a, T>,
+    value: *mut U,
+    _marker: PhantomData<*mut ()>,
+}
+
+unsafe impl<'a, T: ?Sized + Sync, U: ?Sized + Sync> Sync for MappedPinRwLockWriteGuard<'a, T, U> {}
+
+/// A write guard which keeps its own strong reference to the value, and so
+/// is not tied to the lifetime of the `PinArc` it came from.
+pub struct OwnedPinWriteGuard<T: ?Sized + 'static> {
+    // Declared first so that the lock is released before the reference is dropped
+    inner: RwLockWriteGuard<'static, T>,
+    token: PinToken,
+    _arc: Arc<RwLock<T>>,
 }
 
 impl<T> PinArc<T> {
     /// Allocate memory on the heap, move the data into it and pin it.
     pub fn new(data: T) -> PinArc<T> {
-        PinArc { inner: Arc::new(RwLock::new(data)) }
+        let inner = Arc::new(RwLock::new(data));
+        leak_check::register(&inner);
+        PinArc { inner }
+    }
+
+    /// Allocate and pin memory for a value, without initializing it.
+    ///
+    /// The value can then be initialized in place through a write guard,
+    /// for example using `MaybeUninit::pinned_ptr`, before `assume_init`
+    /// is called.
+    pub fn new_uninit() -> PinArc<MaybeUninit<T>> {
+        PinArc::new(MaybeUninit::uninitialized())
+    }
+
+    /// Construct one child per item, each holding a weak reference to `parent`.
+    ///
+    /// The parent is only downgraded once, and the resulting `PinWeak` is
+    /// cloned for each child.
+    pub fn new_children<P: ?Sized, I, F>(parent: &PinArc<P>, items: I, mut f: F) -> Vec<PinArc<T>>
+        where I: IntoIterator, F: FnMut(PinWeak<P>, I::Item) -> T
+    {
+        let weak = PinArc::downgrade(parent);
+        items.into_iter().map(|item| PinArc::new(f(weak.clone(), item))).collect()
+    }
+}
+
+impl<T> PinArc<MaybeUninit<T>> {
+    /// Convert to a `PinArc<T>` once the value has been initialized.
+    ///
+    /// This function is unsafe. Users must guarantee that the value has been
+    /// fully initialized, as it will be treated as a valid `T` from now on.
+    /// All other strong and weak references to the allocation continue to
+    /// refer to it as a `MaybeUninit<T>`, and must not be used to
+    /// uninitialize it.
+    pub unsafe fn assume_init(this: PinArc<MaybeUninit<T>>) -> PinArc<T> {
+        PinArc::from_raw(PinArc::into_raw(this) as *const RwLock<T>)
+    }
+}
+
+impl<T: Unpin> PinArc<T> {
+    /// Returns the inner value, provided this is the only strong reference
+    /// to it. Otherwise the handle is dropped and `None` is returned.
+    ///
+    /// If the lock is poisoned, the value is returned regardless.
+    pub fn into_inner(this: PinArc<T>) -> Option<T> {
+        let inner = PinArc::take_inner(this);
+        leak_check::release(&inner);
+        Arc::try_unwrap(inner).ok()
+            .map(|lock| lock.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Clone the value into a new, independent allocation.
+    ///
+    /// Unlike `clone`, which returns another handle to the same value, the
+    /// result does not share anything with this handle. If the lock is
+    /// poisoned, the value is cloned regardless.
+    pub fn deep_clone(this: &Self) -> PinArc<T> where T: Clone {
+        let value = this.read().unwrap_or_else(PoisonError::into_inner).clone();
+        PinArc::new(value)
+    }
+
+    /// Replace the value with `value`, returning the previous one.
+    ///
+    /// If the lock is poisoned, the value is replaced regardless, and the
+    /// previous value is returned inside the error.
+    pub fn replace(&self, value: T) -> LockResult<T> {
+        match self.inner.write() {
+            Ok(mut guard) => Ok(mem::replace(&mut *guard, value)),
+            Err(p) => Err(PoisonError::new(mem::replace(&mut *p.into_inner(), value))),
+        }
+    }
+
+    /// Exchange the values of two allocations.
+    ///
+    /// As with `move_into`, both locks are taken in order of address, and
+    /// nothing is exchanged if either lock is poisoned, or if both handles
+    /// point to the same allocation.
+    pub fn swap(&self, other: &Self) -> LockResult<()> {
+        if PinArc::ptr_eq(self, other) {
+            return Ok(());
+        }
+        match PinArc::write_pair(self, other) {
+            (Ok(mut guard), Ok(mut other_guard)) => {
+                mem::swap(&mut *guard, &mut *other_guard);
+                Ok(())
+            }
+            _ => Err(PoisonError::new(())),
+        }
+    }
+
+    /// Move the value out of a `PinBox` into a new `PinArc`.
+    ///
+    /// The value is moved to a new allocation, which is only allowed
+    /// because it is `Unpin`.
+    pub fn from_pin_box(boxed: PinBox<T>) -> PinArc<T> {
+        PinArc::new(PinBox::into_inner(boxed))
+    }
+
+    /// Like `from_pin_box`, but for the standard library's `PinBox`.
+    pub fn from_std_pin_box(boxed: boxed::PinBox<T>) -> PinArc<T> {
+        let boxed: Box<T> = boxed.into();
+        PinArc::new(*boxed)
+    }
+
+    /// Move the value into a new `PinRc`, provided this is the only strong
+    /// reference to it. Otherwise the original `PinArc` is returned.
+    ///
+    /// If the lock is poisoned, the value is moved regardless.
+    pub fn into_rc(this: PinArc<T>) -> Result<PinRc<T>, PinArc<T>> {
+        let inner = PinArc::take_inner(this);
+        leak_check::release(&inner);
+        match Arc::try_unwrap(inner) {
+            Ok(lock) => Ok(PinRc::new(lock.into_inner().unwrap_or_else(PoisonError::into_inner))),
+            Err(inner) => Err(PinArc::from_inner(inner)),
+        }
+    }
+}
+
+impl<T: Unpin + Default> PinArc<T> {
+    /// Move the value out of `src`, leaving the default value in its place,
+    /// and store it in `dst`, dropping the value previously there.
+    ///
+    /// Both locks are taken in order of address, so concurrent moves in
+    /// opposite directions cannot deadlock. Nothing is moved if either lock
+    /// is poisoned, or if `src` and `dst` are the same allocation.
+    pub fn move_into(src: &Self, dst: &Self) -> LockResult<()> {
+        if PinArc::ptr_eq(src, dst) {
+            return Ok(());
+        }
+        match PinArc::write_pair(src, dst) {
+            (Ok(mut src_guard), Ok(mut dst_guard)) => {
+                *dst_guard = mem::replace(&mut *src_guard, T::default());
+                Ok(())
+            }
+            _ => Err(PoisonError::new(())),
+        }
     }
 }
 
 impl<T: Unpin + ?Sized> PinArc<T> {
     pub fn safe_unpin(this: PinArc<T>) -> Arc<RwLock<T>> {
-        this.inner
+        let inner = PinArc::take_inner(this);
+        leak_check::release(&inner);
+        inner
+    }
+
+    /// Borrow the underlying `Arc`, without giving up this handle.
+    #[inline]
+    pub fn inner(&self) -> &Arc<RwLock<T>> {
+        &self.inner
     }
 }
 
 impl<T: ?Sized> PinArc<T> {
+    /// Wrap an existing reference, accounting for it if leak checking is enabled.
+    fn from_inner(inner: Arc<RwLock<T>>) -> Self {
+        leak_check::acquire(&inner);
+        PinArc { inner }
+    }
+
+    /// Lock two different allocations for writing, in order of address.
+    fn write_pair<'a>(a: &'a Self, b: &'a Self)
+        -> (LockResult<RwLockWriteGuard<'a, T>>, LockResult<RwLockWriteGuard<'a, T>>)
+    {
+        if alloc_key(&*a.inner) < alloc_key(&*b.inner) {
+            let a_guard = a.inner.write();
+            (a_guard, b.inner.write())
+        } else {
+            let b_guard = b.inner.write();
+            (a.inner.write(), b_guard)
+        }
+    }
+
+    /// Lock two different allocations for reading, in order of address.
+    fn read_pair<'a>(a: &'a Self, b: &'a Self)
+        -> (LockResult<RwLockReadGuard<'a, T>>, LockResult<RwLockReadGuard<'a, T>>)
+    {
+        if alloc_key(&*a.inner) < alloc_key(&*b.inner) {
+            let a_guard = a.inner.read();
+            (a_guard, b.inner.read())
+        } else {
+            let b_guard = b.inner.read();
+            (a.inner.read(), b_guard)
+        }
+    }
+
+    /// Take the inner reference without running `Drop`.
+    fn take_inner(this: Self) -> Arc<RwLock<T>> {
+        let inner = unsafe { ptr::read(&this.inner) };
+        mem::forget(this);
+        inner
+    }
+
+    /// Consume the handle, returning a pointer to the `RwLock` inside the
+    /// `Arc` allocation. The strong reference is kept alive until the
+    /// pointer is passed back to `from_raw`.
     pub fn into_raw(this: Self) -> *const RwLock<T> {
-        Arc::into_raw(this.inner)
+        let inner = PinArc::take_inner(this);
+        leak_check::forget(&inner);
+        Arc::into_raw(inner)
     }
 
+    /// Consume the handle without releasing its reference, returning a
+    /// shared reference to the value which lives forever.
+    ///
+    /// This only succeeds if this is the only reference to the value, strong
+    /// or weak, as otherwise the other handles could never lock it for
+    /// writing again. Otherwise the handle is returned. The value is reached
+    /// without locking, so if the lock is poisoned, it is returned regardless.
+    pub fn leak(mut this: Self) -> Result<&'static T, Self> where T: 'static {
+        let value = Arc::get_mut(&mut this.inner)
+            .map(|lock| lock.get_mut().unwrap_or_else(PoisonError::into_inner) as *const T);
+        let value = match value {
+            Some(value) => value,
+            None => return Err(this),
+        };
+        let inner = PinArc::take_inner(this);
+        leak_check::untrack(&inner);
+        mem::forget(inner);
+        // The allocation is never freed, and there are no other handles
+        // which could lock it
+        Ok(unsafe { &*value })
+    }
+
+    /// Reconstruct a handle from a pointer returned by `into_raw`.
+    ///
+    /// This function is unsafe. The pointer must point to the `RwLock`
+    /// inside a live `Arc<RwLock<T>>` allocation, such as one returned by
+    /// `PinArc::into_raw` or `Arc::into_raw`, and this takes ownership of
+    /// one of its strong references. If the allocation did not come from a
+    /// `PinArc`, its value must not have been moved since it was first
+    /// accessed as pinned, and must only be accessed through `PinArc`s from
+    /// now on. The pointer must have the same type `T`, or one with the same
+    /// layout, as the allocation.
     pub unsafe fn from_raw(ptr: *const RwLock<T>) -> Self {
-        PinArc { inner: Arc::from_raw(ptr) }
+        PinArc::from_inner(Arc::from_raw(ptr))
+    }
+
+    /// Increment the strong reference count of an allocation, given
+    /// a pointer obtained from `into_raw`.
+    ///
+    /// This function is unsafe. The pointer must have come from `into_raw`,
+    /// and the allocation must still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const RwLock<T>) {
+        // Go through the handle conversions, so that leak checking sees
+        // both raw references
+        let arc = PinArc::from_raw(ptr);
+        PinArc::into_raw(arc.clone());
+        PinArc::into_raw(arc);
+    }
+
+    /// Decrement the strong reference count of an allocation, given
+    /// a pointer obtained from `into_raw`. The value is dropped if this
+    /// was the last strong reference.
+    ///
+    /// This function is unsafe. The pointer must have come from `into_raw`,
+    /// and must own one strong reference to the allocation, which is
+    /// given up by this call.
+    pub unsafe fn decrement_strong_count(ptr: *const RwLock<T>) {
+        drop(PinArc::from_raw(ptr));
     }
 
     /// Convert this PinArc into an unpinned Arc.
@@ -52,7 +436,9 @@ impl<T: ?Sized> PinArc<T> {
     /// moved out of the Arc.
     #[inline]
     pub unsafe fn unpin(this: PinArc<T>) -> Arc<RwLock<T>> {
-        this.inner
+        let inner = PinArc::take_inner(this);
+        leak_check::release(&inner);
+        inner
     }
 
     #[inline]
@@ -60,6 +446,14 @@ impl<T: ?Sized> PinArc<T> {
         PinWeak { inner: Arc::downgrade(&this.inner) }
     }
 
+    /// Downgrade, and pass the resulting `PinWeak` to `f`.
+    #[inline]
+    pub fn downgrade_and<R, F>(this: &Self, f: F) -> R
+        where F: FnOnce(PinWeak<T>) -> R
+    {
+        f(PinArc::downgrade(this))
+    }
+
     #[inline]
     pub fn weak_count(this: &Self) -> usize {
         Arc::weak_count(&this.inner)
@@ -75,6 +469,59 @@ impl<T: ?Sized> PinArc<T> {
         Arc::ptr_eq(&this.inner, &other.inner)
     }
 
+    /// Compare the values of two handles.
+    ///
+    /// Both read locks are taken in order of address, so that this cannot
+    /// deadlock with another thread locking the same pair. Handles to the
+    /// same allocation are equal without taking either lock. If either
+    /// lock is poisoned, the values are compared regardless, and the result
+    /// is returned inside the error.
+    pub fn value_eq(a: &Self, b: &Self) -> LockResult<bool> where T: PartialEq {
+        if PinArc::ptr_eq(a, b) {
+            return Ok(true);
+        }
+        let (a_guard, b_guard) = PinArc::read_pair(a, b);
+        let poisoned = a_guard.is_err() || b_guard.is_err();
+        let a_guard = a_guard.unwrap_or_else(PoisonError::into_inner);
+        let b_guard = b_guard.unwrap_or_else(PoisonError::into_inner);
+        let eq = *a_guard == *b_guard;
+        if poisoned {
+            Err(PoisonError::new(eq))
+        } else {
+            Ok(eq)
+        }
+    }
+
+    /// Returns true if `weak` refers to the same allocation as this handle.
+    ///
+    /// `weak` is upgraded for the comparison, but as this handle keeps the
+    /// allocation alive, the extra strong reference is only momentary and
+    /// is never the last one. A default `PinWeak` never compares equal.
+    #[inline]
+    pub fn weak_ptr_eq(this: &Self, weak: &PinWeak<T>) -> bool {
+        weak.inner.upgrade().map_or(false, |inner| Arc::ptr_eq(&this.inner, &inner))
+    }
+
+    /// Get an identity for the allocation, which can be stored and compared
+    /// without keeping the value alive.
+    ///
+    /// Ids of different allocations never compare equal, even when one
+    /// allocation is created at the address of another which has been freed.
+    /// An id holds a weak reference, so the memory of the allocation is not
+    /// released, and its address cannot be reused, while any id for it
+    /// exists. Like any weak reference, this means it is not unique for
+    /// `modify` or `get_mut_unchecked`.
+    #[inline]
+    pub fn id(&self) -> PinId<T> {
+        PinId { key: alloc_key(&*self.inner), _alloc: Arc::downgrade(&self.inner) }
+    }
+
+    /// Returns true if `id` is the identity of this allocation.
+    #[inline]
+    pub fn has_id(&self, id: &PinId<T>) -> bool {
+        alloc_key(&*self.inner) == id.key
+    }
+
     #[inline]
     pub fn read(&self) -> LockResult<PinRwLockReadGuard<T>> {
         match self.inner.read() {
@@ -86,11 +533,78 @@ impl<T: ?Sized> PinArc<T> {
     #[inline]
     pub fn write(&self) -> LockResult<PinRwLockWriteGuard<T>> {
         match self.inner.write() {
-            Ok(inner) => Ok(PinRwLockWriteGuard { inner }),
-            Err(p) => Err(PoisonError::new(PinRwLockWriteGuard { inner: p.into_inner() })),
+            Ok(inner) => Ok(PinRwLockWriteGuard {
+                inner,
+                token: PinToken::new(&self.inner),
+                hold: HoldTimer::unlimited(),
+            }),
+            Err(p) => Err(PoisonError::new(PinRwLockWriteGuard {
+                inner: p.into_inner(),
+                token: PinToken::new(&self.inner),
+                hold: HoldTimer::unlimited(),
+            })),
+        }
+    }
+
+    /// Like `write`, but in debug builds the guard panics when it is dropped
+    /// if the lock was held for longer than `max_hold`.
+    ///
+    /// In release builds this is the same as `write`.
+    pub fn write_with_max_hold(&self, max_hold: Duration) -> LockResult<PinRwLockWriteGuard<T>> {
+        match self.write() {
+            Ok(mut guard) => {
+                guard.hold = HoldTimer::new(max_hold);
+                Ok(guard)
+            }
+            Err(p) => {
+                let mut guard = p.into_inner();
+                guard.hold = HoldTimer::new(max_hold);
+                Err(PoisonError::new(guard))
+            }
         }
     }
 
+    /// Lock for reading, and project the guard to part of the value.
+    pub fn read_map<U: ?Sized, F>(&self, f: F) -> LockResult<MappedPinRwLockReadGuard<T, U>>
+        where F: FnOnce(&T) -> &U
+    {
+        match self.read() {
+            Ok(guard) => Ok(PinRwLockReadGuard::map(guard, f)),
+            Err(p) => Err(PoisonError::new(PinRwLockReadGuard::map(p.into_inner(), f))),
+        }
+    }
+
+    /// Lock for writing, and project the guard to part of the value, which
+    /// remains pinned.
+    pub fn write_map<U: ?Sized, F>(&self, f: F) -> LockResult<MappedPinRwLockWriteGuard<T, U>>
+        where F: FnOnce(Pin<T>) -> Pin<U>
+    {
+        match self.write() {
+            Ok(guard) => Ok(PinRwLockWriteGuard::map(guard, f)),
+            Err(p) => Err(PoisonError::new(PinRwLockWriteGuard::map(p.into_inner(), f))),
+        }
+    }
+
+    /// Call `f` with a pinned reference to the value.
+    ///
+    /// If this is the only reference to the value, strong or weak, the lock
+    /// is not needed and is bypassed entirely. Otherwise this waits for the
+    /// write lock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    pub fn modify<R, F>(&mut self, f: F) -> R
+        where F: FnOnce(Pin<T>) -> R
+    {
+        if let Some(lock) = Arc::get_mut(&mut self.inner) {
+            let value = lock.get_mut().expect("PinArc lock poisoned");
+            return f(unsafe { Pin::new_unchecked(value) });
+        }
+        let mut guard = self.write().expect("PinArc lock poisoned");
+        f(guard.as_pin())
+    }
+
     #[inline]
     pub fn try_read(&self) -> TryLockResult<PinRwLockReadGuard<T>> {
         match self.inner.try_read() {
@@ -102,30 +616,192 @@ impl<T: ?Sized> PinArc<T> {
         }
     }
 
+    /// Lock for reading, and call `f` with a shared reference to the value.
+    ///
+    /// A shared reference can never be used to move the value, so there is
+    /// no separate pinned form of it. If the lock is poisoned, `f` is still
+    /// called, and its result is returned inside the error.
+    pub fn with_read<R, F>(&self, f: F) -> LockResult<R>
+        where F: FnOnce(&T) -> R
+    {
+        match self.read() {
+            Ok(guard) => Ok(f(&*guard)),
+            Err(p) => Err(PoisonError::new(f(&*p.into_inner()))),
+        }
+    }
+
+    /// Like `read`, but the guard holds its own strong reference to the
+    /// value rather than borrowing this handle.
+    pub fn read_owned(&self) -> LockResult<OwnedPinReadGuard<T>> where T: 'static {
+        let arc = self.inner.clone();
+        // The guard keeps the `Arc` alive for as long as it borrows the lock
+        let lock: &'static RwLock<T> = unsafe { &*(&*arc as *const RwLock<T>) };
+        match lock.read() {
+            Ok(inner) => Ok(OwnedPinReadGuard { inner, _arc: arc }),
+            Err(p) => Err(PoisonError::new(OwnedPinReadGuard { inner: p.into_inner(), _arc: arc })),
+        }
+    }
+
+    /// Like `write`, but the guard holds its own strong reference to the
+    /// value rather than borrowing this handle.
+    pub fn write_owned(&self) -> LockResult<OwnedPinWriteGuard<T>> where T: 'static {
+        let arc = self.inner.clone();
+        // The guard keeps the `Arc` alive for as long as it borrows the lock
+        let lock: &'static RwLock<T> = unsafe { &*(&*arc as *const RwLock<T>) };
+        let token = PinToken::new(lock);
+        match lock.write() {
+            Ok(inner) => Ok(OwnedPinWriteGuard { inner, token, _arc: arc }),
+            Err(p) => Err(PoisonError::new(OwnedPinWriteGuard {
+                inner: p.into_inner(),
+                token,
+                _arc: arc,
+            })),
+        }
+    }
+
     #[inline]
     pub fn try_write(&self) -> TryLockResult<PinRwLockWriteGuard<T>> {
         match self.inner.try_write() {
-            Ok(inner) => Ok(PinRwLockWriteGuard { inner }),
+            Ok(inner) => Ok(PinRwLockWriteGuard {
+                inner,
+                token: PinToken::new(&self.inner),
+                hold: HoldTimer::unlimited(),
+            }),
             Err(TryLockError::Poisoned(p)) => Err(TryLockError::Poisoned(PoisonError::new(
-                PinRwLockWriteGuard { inner: p.into_inner() }
+                PinRwLockWriteGuard {
+                    inner: p.into_inner(),
+                    token: PinToken::new(&self.inner),
+                    hold: HoldTimer::unlimited(),
+                }
             ))),
             Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
         }
     }
 
+    /// Like `read`, but first retries `try_read` up to `spins` times, which
+    /// is cheaper than blocking when the lock is only held briefly.
+    ///
+    /// Panics if the lock is poisoned.
+    pub fn spin_read(&self, spins: u32) -> PinRwLockReadGuard<T> {
+        for _ in 0..spins {
+            match self.try_read() {
+                Ok(guard) => return guard,
+                Err(TryLockError::WouldBlock) => atomic::spin_loop_hint(),
+                Err(TryLockError::Poisoned(_)) => panic!("PinArc lock poisoned"),
+            }
+        }
+        self.read().expect("PinArc lock poisoned")
+    }
+
+    /// Like `write`, but first retries `try_write` up to `spins` times, which
+    /// is cheaper than blocking when the lock is only held briefly.
+    ///
+    /// Panics if the lock is poisoned.
+    pub fn spin_write(&self, spins: u32) -> PinRwLockWriteGuard<T> {
+        for _ in 0..spins {
+            match self.try_write() {
+                Ok(guard) => return guard,
+                Err(TryLockError::WouldBlock) => atomic::spin_loop_hint(),
+                Err(TryLockError::Poisoned(_)) => panic!("PinArc lock poisoned"),
+            }
+        }
+        self.write().expect("PinArc lock poisoned")
+    }
+
+    /// Get a pinned reference to the value without taking the lock.
+    ///
+    /// This function is unsafe. Users must guarantee that this is the only
+    /// strong reference and that there are no weak references, which is
+    /// asserted in debug builds. `RwLock` has no way to reach the value
+    /// through a shared reference without locking, so the reference is
+    /// obtained with `Arc::get_mut`, and a violation panics rather than
+    /// going unnoticed. Panics if the lock is poisoned.
+    #[inline]
+    pub unsafe fn get_mut_unchecked(this: &mut Self) -> Pin<T> {
+        debug_assert!(Arc::strong_count(&this.inner) == 1 && Arc::weak_count(&this.inner) == 0,
+            "PinArc::get_mut_unchecked called on a shared PinArc");
+        let lock = Arc::get_mut(&mut this.inner).expect("PinArc is not unique");
+        Pin::new_unchecked(lock.get_mut().expect("PinArc lock poisoned"))
+    }
+
     #[inline]
     pub fn is_poisoned(&self) -> bool {
         self.inner.is_poisoned()
     }
+
+    /// Returns true while a write guard for this value has handed out a
+    /// pinned reference via `as_pin` and has not yet been dropped.
+    ///
+    /// This is a debugging aid, and is only available with the `pin-tracking`
+    /// feature, as tracking takes a global lock whenever a pointer is pinned.
+    #[cfg(feature = "pin-tracking")]
+    pub fn is_pin_active(&self) -> bool {
+        PinToken::is_active(&self.inner)
+    }
 }
 
 impl<T: ?Sized> Clone for PinArc<T> {
     #[inline]
     fn clone(&self) -> Self {
-        PinArc { inner: self.inner.clone() }
+        PinArc::from_inner(self.inner.clone())
+    }
+
+    /// Does nothing if both handles already point to the same allocation.
+    #[inline]
+    fn clone_from(&mut self, source: &Self) {
+        if !PinArc::ptr_eq(self, source) {
+            *self = source.clone();
+        }
+    }
+}
+
+/// Handles are compared by the address of their allocation, not by value,
+/// so comparisons never need to take the lock.
+impl<T: ?Sized> PartialEq for PinArc<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        alloc_key(&*self.inner) == alloc_key(&*other.inner)
+    }
+}
+
+impl<T: ?Sized> Eq for PinArc<T> {}
+
+impl<T: ?Sized> PartialOrd for PinArc<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: ?Sized> Ord for PinArc<T> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        alloc_key(&*self.inner).cmp(&alloc_key(&*other.inner))
     }
 }
 
+impl<T: ?Sized> Hash for PinArc<T> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        alloc_key(&*self.inner).hash(state)
+    }
+}
+
+/// Only the address of the allocation is used when a handle is dropped, so
+/// the value may already be dangling. This keeps the drop check the same as
+/// for the inner `Arc`, so enabling the `leak-check` feature in any crate
+/// never stops code elsewhere from compiling.
+#[cfg(feature = "leak-check")]
+unsafe impl<#[may_dangle] T: ?Sized> Drop for PinArc<T> {
+    fn drop(&mut self) {
+        leak_check::release(&self.inner);
+    }
+}
+
+/// Allows a handle to a sized value to be converted into one to an unsized
+/// value, such as a slice or trait object.
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<PinArc<U>> for PinArc<T> {}
+
 impl<T> From<T> for PinArc<T> {
     #[inline]
     fn from(t: T) -> Self {
@@ -133,14 +809,100 @@ impl<T> From<T> for PinArc<T> {
     }
 }
 
+/// Exposing the `RwLock` would allow the value to be moved out, so this
+/// is only available for `Unpin` values.
+impl<T: Unpin + ?Sized> AsRef<RwLock<T>> for PinArc<T> {
+    #[inline]
+    fn as_ref(&self) -> &RwLock<T> {
+        &*self.inner
+    }
+}
+
+impl<T: Unpin + ?Sized> borrow::Borrow<RwLock<T>> for PinArc<T> {
+    #[inline]
+    fn borrow(&self) -> &RwLock<T> {
+        &*self.inner
+    }
+}
+
+/// Moves the boxed value into a new allocation, so this is only available
+/// for sized `T`.
+impl<T> From<Box<T>> for PinArc<T> {
+    #[inline]
+    fn from(boxed: Box<T>) -> Self {
+        PinArc::new(*boxed)
+    }
+}
+
 impl<T> From<Arc<RwLock<T>>> for PinArc<T> {
     #[inline]
     fn from(inner: Arc<RwLock<T>>) -> Self {
-        PinArc { inner }
+        PinArc::from_inner(inner)
+    }
+}
+
+/// Formats the value, or a placeholder if it is currently write-locked.
+impl<T: ?Sized + fmt::Display> fmt::Display for PinArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner.try_read() {
+            Ok(value) => fmt::Display::fmt(&*value, f),
+            Err(TryLockError::Poisoned(p)) => fmt::Display::fmt(&*p.into_inner(), f),
+            Err(TryLockError::WouldBlock) => f.write_str("<locked>"),
+        }
+    }
+}
+
+impl<T: ?Sized> SharedHandle for PinArc<T> {
+    type Target = T;
+
+    #[inline]
+    fn addr(this: &Self) -> usize {
+        alloc_key(&*this.inner)
+    }
+    /// A poisoned lock is ignored, as reading the value cannot observe a
+    /// broken invariant any more than `Display` can.
+    #[inline]
+    fn with_value<R, F: FnOnce(&T) -> R>(this: &Self, f: F) -> R {
+        f(&*this.inner.read().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for PinArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ptr: *const RwLock<T> = &*self.inner;
+        fmt::Pointer::fmt(&ptr, f)
+    }
+}
+
+impl<'a, T> PinRwLockReadGuard<'a, [T]> {
+    /// Returns a reference to an element of the slice, or `None` if the
+    /// index is out of bounds. The read lock remains held for as long as
+    /// the reference is in use.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index)
+    }
+}
+
+/// Guards compare and hash by the borrowed value, unlike the handles
+/// they were obtained from.
+impl<'a, T: ?Sized + PartialEq> PartialEq for PinRwLockReadGuard<'a, T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        *self.inner == *other.inner
+    }
+}
+
+impl<'a, T: ?Sized + Eq> Eq for PinRwLockReadGuard<'a, T> {}
+
+impl<'a, T: ?Sized + Hash> Hash for PinRwLockReadGuard<'a, T> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (*self.inner).hash(state)
     }
 }
 
-impl<'a, T> Deref for PinRwLockReadGuard<'a, T> {
+impl<'a, T: ?Sized> Deref for PinRwLockReadGuard<'a, T> {
     type Target = T;
 
     #[inline]
@@ -149,18 +911,116 @@ impl<'a, T> Deref for PinRwLockReadGuard<'a, T> {
     }
 }
 
+impl<'a, T: ?Sized> PinRwLockReadGuard<'a, T> {
+    /// Project the guard to part of the value.
+    pub fn map<U: ?Sized, F>(orig: Self, f: F) -> MappedPinRwLockReadGuard<'a, T, U>
+        where F: FnOnce(&T) -> &U
+    {
+        let value = f(&*orig) as *const U;
+        MappedPinRwLockReadGuard { _guard: orig, value, _marker: PhantomData }
+    }
+}
+
 impl<'a, T: ?Sized> PinRwLockWriteGuard<'a, T> {
     #[inline]
     pub fn as_pin(&mut self) -> Pin<T> {
+        self.token.activate();
         unsafe { Pin::new_unchecked(&mut *self.inner) }
     }
+    /// Re-borrow the guard as a pinned reference. This is the same as
+    /// `as_pin`, and can be called any number of times while the guard is
+    /// held.
+    #[inline]
+    pub fn reborrow(&mut self) -> Pin<T> {
+        self.as_pin()
+    }
+    /// Re-borrow the guard as a shared reference, which cannot be used to
+    /// move the value.
+    #[inline]
+    pub fn reborrow_shared(&self) -> &T {
+        &*self.inner
+    }
     #[inline]
     pub unsafe fn get_mut(this: &mut Self) -> &mut T {
         &mut *this.inner
     }
+
+    /// Project the guard to part of the value, which remains pinned.
+    pub fn map<U: ?Sized, F>(orig: Self, f: F) -> MappedPinRwLockWriteGuard<'a, T, U>
+        where F: FnOnce(Pin<T>) -> Pin<U>
+    {
+        match PinRwLockWriteGuard::try_map(orig, |pin| Ok::<_, ()>(f(pin))) {
+            Ok(mapped) => mapped,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Like `map`, but the projection may fail, for example if the value is
+    /// a different enum variant. In that case the original guard is
+    /// returned intact along with the error, so that another projection
+    /// can be tried.
+    pub fn try_map<U: ?Sized, E, F>(mut orig: Self, f: F)
+        -> Result<MappedPinRwLockWriteGuard<'a, T, U>, (Self, E)>
+        where F: FnOnce(Pin<T>) -> Result<Pin<U>, E>
+    {
+        let projected = f(orig.as_pin()).map(|mut pin_u| unsafe { Pin::get_mut(&mut pin_u) as *mut U });
+        match projected {
+            Ok(value) => Ok(MappedPinRwLockWriteGuard { guard: orig, value, _marker: PhantomData }),
+            Err(e) => Err((orig, e)),
+        }
+    }
+
+    /// Split the locked value into a pinned field and a shared borrow of
+    /// another field, for example to drive a pinned generator while reading
+    /// sibling configuration.
+    ///
+    /// This function is unsafe. Users must guarantee that `f` only projects
+    /// to the fields of the value, without moving anything, and that the
+    /// first field is never moved out of the value in future.
+    #[inline]
+    pub unsafe fn map_split<'b, A: ?Sized, B: ?Sized, F>(this: &'b mut Self, f: F) -> (Pin<'b, A>, &'b B)
+        where F: FnOnce(&'b mut T) -> (&'b mut A, &'b B)
+    {
+        this.token.activate();
+        let (pinned, shared) = f(&mut *this.inner);
+        (Pin::new_unchecked(pinned), shared)
+    }
+}
+
+impl<'a, T: ?Sized> PinRwLockWriteGuard<'a, Option<Box<T>>> {
+    /// Get a pinned reference to the boxed value, if there is one.
+    ///
+    /// The boxed value has a stable address, and as the guard only allows
+    /// pinned access, it cannot be moved out of the `Option` either.
+    #[inline]
+    pub fn as_pin_mut(&mut self) -> Option<Pin<T>> {
+        self.token.activate();
+        match *self.inner {
+            Some(ref mut boxed) => Some(unsafe { Pin::new_unchecked(&mut **boxed) }),
+            None => None,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinRwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &*self.inner
+    }
+}
+
+/// Values which are `Unpin` may be freely moved, so no pinning is needed
+/// to mutate them.
+impl<'a, T: ?Sized + Unpin> DerefMut for PinRwLockWriteGuard<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.inner
+    }
 }
 
-impl<'a, T> Deref for PinRwLockWriteGuard<'a, T> {
+impl<T: ?Sized> Deref for OwnedPinReadGuard<T> {
     type Target = T;
 
     #[inline]
@@ -169,10 +1029,122 @@ impl<'a, T> Deref for PinRwLockWriteGuard<'a, T> {
     }
 }
 
+impl<T: ?Sized> OwnedPinWriteGuard<T> {
+    #[inline]
+    pub fn as_pin(&mut self) -> Pin<T> {
+        self.token.activate();
+        unsafe { Pin::new_unchecked(&mut *self.inner) }
+    }
+    #[inline]
+    pub unsafe fn get_mut(this: &mut Self) -> &mut T {
+        &mut *this.inner
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedPinWriteGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &*self.inner
+    }
+}
+
+impl<T: ?Sized + Unpin> DerefMut for OwnedPinWriteGuard<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.inner
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> Deref for MappedPinRwLockReadGuard<'a, T, U> {
+    type Target = U;
+
+    #[inline]
+    fn deref(&self) -> &U {
+        unsafe { &*self.value }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> MappedPinRwLockWriteGuard<'a, T, U> {
+    #[inline]
+    pub fn as_pin(&mut self) -> Pin<U> {
+        self.guard.token.activate();
+        unsafe { Pin::new_unchecked(&mut *self.value) }
+    }
+    #[inline]
+    pub unsafe fn get_mut(this: &mut Self) -> &mut U {
+        &mut *this.value
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> Deref for MappedPinRwLockWriteGuard<'a, T, U> {
+    type Target = U;
+
+    #[inline]
+    fn deref(&self) -> &U {
+        unsafe { &*self.value }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + Unpin> DerefMut for MappedPinRwLockWriteGuard<'a, T, U> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.value }
+    }
+}
+
 impl<T: ?Sized> PinWeak<T> {
     #[inline]
     pub fn upgrade(&self) -> Option<PinArc<T>> {
-        self.inner.upgrade().map(|inner| PinArc { inner })
+        self.inner.upgrade().map(PinArc::from_inner)
+    }
+
+    /// Consume the weak reference, moving it into a heap allocation of its
+    /// own and returning a pointer to that, which keeps it alive until it is
+    /// passed back to `from_boxed_raw`.
+    ///
+    /// The pointer is to the box, not to the value. `std::sync::Weak` has no
+    /// `into_raw` on this toolchain, so the weak reference itself cannot be
+    /// turned into a pointer, and each call costs an allocation.
+    pub fn into_boxed_raw(this: Self) -> *mut PinWeak<T> {
+        Box::into_raw(Box::new(this))
+    }
+
+    /// Reconstruct a weak reference from a pointer returned by
+    /// `into_boxed_raw`, freeing the box.
+    ///
+    /// This function is unsafe. The pointer must have come from
+    /// `into_boxed_raw`, and must not be used again afterwards.
+    pub unsafe fn from_boxed_raw(ptr: *mut PinWeak<T>) -> Self {
+        *Box::from_raw(ptr)
+    }
+
+    /// Upgrade if the value is still alive, or otherwise call `f` to
+    /// construct a replacement.
+    #[inline]
+    pub fn upgrade_or_else<F>(&self, f: F) -> PinArc<T>
+        where F: FnOnce() -> PinArc<T>
+    {
+        self.upgrade().unwrap_or_else(f)
+    }
+
+    /// Upgrade, lock the value for writing and run `f` with a pinned
+    /// reference to it. The lock and the temporary strong reference are
+    /// released before returning.
+    ///
+    /// Returns `None` if the value has already been dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    pub fn with_write<R, F>(&self, f: F) -> Option<R>
+        where F: FnOnce(Pin<T>) -> R
+    {
+        self.upgrade().map(|arc| {
+            let mut guard = arc.write().expect("PinArc lock poisoned");
+            f(guard.as_pin())
+        })
     }
 }
 
@@ -197,3 +1169,364 @@ impl<T> Default for PinWeak<T> {
         PinWeak { inner: Weak::default() }
     }
 }
+
+/// An identity for a `PinArc` allocation, which does not keep its value
+/// alive.
+///
+/// See `PinArc::id`.
+pub struct PinId<T: ?Sized> {
+    key: usize,
+    // Keeps the address reserved, so that no other allocation can reuse it
+    _alloc: Weak<RwLock<T>>,
+}
+
+impl<T: ?Sized> Clone for PinId<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        PinId { key: self.key, _alloc: self._alloc.clone() }
+    }
+}
+
+impl<T: ?Sized> PartialEq for PinId<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T: ?Sized> Eq for PinId<T> {}
+
+impl<T: ?Sized> Hash for PinId<T> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for PinId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PinId").field(&format_args!("{:#x}", self.key)).finish()
+    }
+}
+
+/// The address of the allocation, used to identify it. This must be given
+/// the value inside the `Arc` or `Rc`, not the pointer itself.
+pub(crate) fn alloc_key<T: ?Sized>(value: &T) -> usize {
+    value as *const T as *const () as usize
+}
+
+/// Tracks, per allocation, how many write guards currently have a pinned
+/// reference outstanding. Only used for `PinArc::is_pin_active`.
+#[cfg(feature = "pin-tracking")]
+struct PinToken {
+    key: usize,
+    active: bool,
+}
+
+#[cfg(feature = "pin-tracking")]
+global_registry!(fn pin_tokens() -> usize);
+
+#[cfg(feature = "pin-tracking")]
+impl PinToken {
+    fn new<T: ?Sized>(lock: &RwLock<T>) -> PinToken {
+        PinToken { key: alloc_key(lock), active: false }
+    }
+
+    fn is_active<T: ?Sized>(lock: &RwLock<T>) -> bool {
+        let registry = pin_tokens().lock().unwrap_or_else(PoisonError::into_inner);
+        registry.contains_key(&alloc_key(lock))
+    }
+
+    fn activate(&mut self) {
+        if !self.active {
+            let mut registry = pin_tokens().lock().unwrap_or_else(PoisonError::into_inner);
+            *registry.entry(self.key).or_insert(0) += 1;
+            self.active = true;
+        }
+    }
+}
+
+#[cfg(feature = "pin-tracking")]
+impl Drop for PinToken {
+    fn drop(&mut self) {
+        if self.active {
+            let mut registry = pin_tokens().lock().unwrap_or_else(PoisonError::into_inner);
+            let remove = {
+                let count = registry.get_mut(&self.key).expect("pin token not registered");
+                *count -= 1;
+                *count == 0
+            };
+            if remove {
+                registry.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "pin-tracking"))]
+struct PinToken;
+
+#[cfg(not(feature = "pin-tracking"))]
+impl PinToken {
+    #[inline]
+    fn new<T: ?Sized>(_lock: &RwLock<T>) -> PinToken {
+        PinToken
+    }
+
+    #[inline]
+    fn activate(&mut self) {}
+}
+
+/// Asserts, when dropped, that a write guard was not held for longer than
+/// its limit. Only used for `PinArc::write_with_max_hold`.
+#[cfg(debug_assertions)]
+struct HoldTimer {
+    limit: Option<(Instant, Duration)>,
+}
+
+#[cfg(debug_assertions)]
+impl HoldTimer {
+    fn unlimited() -> HoldTimer {
+        HoldTimer { limit: None }
+    }
+
+    fn new(max_hold: Duration) -> HoldTimer {
+        HoldTimer { limit: Some((Instant::now(), max_hold)) }
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for HoldTimer {
+    fn drop(&mut self) {
+        if let Some((start, max_hold)) = self.limit {
+            let held = start.elapsed();
+            // Don't turn an existing panic into an abort
+            if held > max_hold && !thread::panicking() {
+                panic!("PinArc write lock held for {:?}, longer than the limit of {:?}", held, max_hold);
+            }
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+struct HoldTimer;
+
+#[cfg(not(debug_assertions))]
+impl HoldTimer {
+    #[inline]
+    fn unlimited() -> HoldTimer {
+        HoldTimer
+    }
+
+    #[inline]
+    fn new(_max_hold: Duration) -> HoldTimer {
+        HoldTimer
+    }
+}
+
+/// Lock every allocation in `arcs` for writing.
+///
+/// The locks are always taken in order of address, whatever order `arcs`
+/// is in, so concurrent calls on overlapping sets cannot deadlock. The
+/// result has one entry for each handle in `arcs`, in the same order. Where
+/// several handles share an allocation, only the first holds the guard,
+/// and the entries for the rest are `None`. If any lock is poisoned, all of
+/// the guards are still taken, and are returned inside the error.
+pub fn write_all<'a, T: ?Sized>(arcs: &'a [PinArc<T>])
+    -> LockResult<Vec<Option<PinRwLockWriteGuard<'a, T>>>>
+{
+    let mut order: Vec<usize> = (0..arcs.len()).collect();
+    // Stable, so that the first of any duplicates is kept
+    order.sort_by_key(|&i| alloc_key(&*arcs[i].inner));
+    order.dedup_by_key(|i| alloc_key(&*arcs[*i].inner));
+
+    let mut poisoned = false;
+    let mut guards: Vec<_> = arcs.iter().map(|_| None).collect();
+    for i in order {
+        guards[i] = Some(arcs[i].write().unwrap_or_else(|p| {
+            poisoned = true;
+            p.into_inner()
+        }));
+    }
+
+    if poisoned {
+        Err(PoisonError::new(guards))
+    } else {
+        Ok(guards)
+    }
+}
+
+/// Tracks the `PinArc` allocations made on the current thread, so that a
+/// test can check that none of them are leaked.
+///
+/// Allocations are tracked from `PinArc::new` until their last `PinArc` is
+/// dropped or converted into a plain `Arc`. Only allocations made on this
+/// thread while the tracker is alive are tracked, so tests running in
+/// parallel on other threads do not affect the result. Values leaked on
+/// purpose with `PinArc::leak` are not counted.
+///
+/// ```
+/// # use pin_rc::{PinArc, LeakTracker};
+/// let tracker = LeakTracker::new();
+/// let a = PinArc::new(1);
+/// let b = a.clone();
+/// drop((a, b));
+/// tracker.assert_no_leaks();
+/// ```
+#[cfg(feature = "leak-check")]
+pub struct LeakTracker {
+    id: usize,
+    previous: usize,
+    // Trackers belong to the thread they were started on
+    _marker: PhantomData<*mut ()>,
+}
+
+#[cfg(feature = "leak-check")]
+impl LeakTracker {
+    /// Start tracking allocations made on this thread. If another tracker
+    /// is already active, it stops tracking new allocations until this one
+    /// is dropped.
+    pub fn new() -> LeakTracker {
+        let id = leak_check::next_tracker();
+        let previous = leak_check::CURRENT.with(|current| current.replace(id));
+        LeakTracker { id, previous, _marker: PhantomData }
+    }
+
+    /// Panics if any allocations made while this tracker was active are
+    /// still alive, listing their addresses.
+    ///
+    /// This catches reference cycles, and handles which were forgotten or
+    /// converted with `into_raw` and never reclaimed.
+    pub fn assert_no_leaks(self) {
+        let mut leaked = leak_check::live(self.id);
+        if !leaked.is_empty() {
+            leaked.sort();
+            let leaked: Vec<_> = leaked.into_iter().map(|key| format!("{:#x}", key)).collect();
+            panic!("leaked PinArc allocations: {}", leaked.join(", "));
+        }
+    }
+}
+
+#[cfg(feature = "leak-check")]
+impl Drop for LeakTracker {
+    fn drop(&mut self) {
+        leak_check::CURRENT.with(|current| current.set(self.previous));
+        leak_check::untrack_all(self.id);
+    }
+}
+
+/// Counts the live `PinArc` handles for each allocation made by `PinArc::new`
+/// while a `LeakTracker` is active.
+#[cfg(feature = "leak-check")]
+mod leak_check {
+    use std::sync::{RwLock, PoisonError};
+    use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use super::alloc_key;
+
+    /// The tracker an allocation belongs to, and how many handles it has.
+    struct Entry {
+        tracker: usize,
+        handles: usize,
+    }
+
+    thread_local! {
+        /// The active tracker on this thread, or zero if there is none.
+        pub static CURRENT: Cell<usize> = Cell::new(0);
+    }
+
+    static NEXT_TRACKER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+    pub fn next_tracker() -> usize {
+        NEXT_TRACKER.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    global_registry!(fn registry() -> Entry);
+
+    fn update<R, F: FnOnce(&mut HashMap<usize, Entry>) -> R>(f: F) -> R {
+        let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut *registry)
+    }
+
+    /// A new allocation, with a single handle.
+    pub fn register<T: ?Sized>(lock: &RwLock<T>) {
+        let tracker = CURRENT.with(Cell::get);
+        if tracker != 0 {
+            update(|registry| { registry.insert(alloc_key(lock), Entry { tracker, handles: 1 }); });
+        }
+    }
+
+    /// A new handle to an existing allocation.
+    pub fn acquire<T: ?Sized>(lock: &RwLock<T>) {
+        update(|registry| {
+            if let Some(entry) = registry.get_mut(&alloc_key(lock)) {
+                entry.handles += 1;
+            }
+        });
+    }
+
+    /// A handle was dropped, or converted into a plain `Arc`.
+    pub fn release<T: ?Sized>(lock: &RwLock<T>) {
+        update(|registry| {
+            let key = alloc_key(lock);
+            let remove = match registry.get_mut(&key) {
+                Some(entry) => {
+                    entry.handles -= 1;
+                    entry.handles == 0
+                }
+                None => false,
+            };
+            if remove {
+                registry.remove(&key);
+            }
+        });
+    }
+
+    /// A handle was converted into a raw pointer. The allocation remains
+    /// registered until the pointer is converted back and dropped.
+    pub fn forget<T: ?Sized>(lock: &RwLock<T>) {
+        update(|registry| {
+            if let Some(entry) = registry.get_mut(&alloc_key(lock)) {
+                entry.handles -= 1;
+            }
+        });
+    }
+
+    /// The allocation was leaked on purpose, so it is no longer tracked.
+    pub fn untrack<T: ?Sized>(lock: &RwLock<T>) {
+        update(|registry| { registry.remove(&alloc_key(lock)); });
+    }
+
+    /// The addresses of the live allocations belonging to `tracker`.
+    pub fn live(tracker: usize) -> Vec<usize> {
+        update(|registry| {
+            registry.iter().filter(|&(_, entry)| entry.tracker == tracker).map(|(&key, _)| key).collect()
+        })
+    }
+
+    /// Stop tracking every allocation belonging to `tracker`.
+    pub fn untrack_all(tracker: usize) {
+        update(|registry| registry.retain(|_, entry| entry.tracker != tracker));
+    }
+}
+
+#[cfg(not(feature = "leak-check"))]
+mod leak_check {
+    use std::sync::RwLock;
+
+    #[inline]
+    pub fn register<T: ?Sized>(_lock: &RwLock<T>) {}
+
+    #[inline]
+    pub fn acquire<T: ?Sized>(_lock: &RwLock<T>) {}
+
+    #[inline]
+    pub fn release<T: ?Sized>(_lock: &RwLock<T>) {}
+
+    #[inline]
+    pub fn forget<T: ?Sized>(_lock: &RwLock<T>) {}
+
+    #[inline]
+    pub fn untrack<T: ?Sized>(_lock: &RwLock<T>) {}
+}
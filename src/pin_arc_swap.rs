@@ -0,0 +1,74 @@
+use std::mem;
+use std::fmt;
+
+use pin_arc::PinArc;
+use pin_spin_arc::SpinLock;
+
+/// A shared slot holding a `PinArc`, which can be replaced at any time.
+///
+/// This is useful for values such as configuration which are reloaded as a
+/// whole: readers `load` a handle to the current value, and keep seeing a
+/// consistent snapshot for as long as they hold it, even as new values are
+/// `store`d.
+///
+/// The slot itself is protected by a spin lock, which is only ever held for
+/// as long as it takes to clone or swap a handle. In particular, none of
+/// the operations take the lock on the value itself.
+///
+/// This is not lock-free: every operation takes the spin lock, so a `load`
+/// spins while another thread is partway through a `store`, and a thread
+/// which is descheduled while holding the lock stalls the others. Swapping
+/// the pointer itself atomically, as `arc-swap` does, would need deferred
+/// reclamation of the old handles, which this crate does not implement.
+/// The type is always available, rather than behind a feature.
+pub struct PinArcSwap<T: ?Sized> {
+    current: SpinLock<PinArc<T>>
+}
+
+impl<T: ?Sized> PinArcSwap<T> {
+    pub fn new(initial: PinArc<T>) -> PinArcSwap<T> {
+        PinArcSwap { current: SpinLock::new(initial) }
+    }
+
+    /// Get a handle to the current value.
+    pub fn load(&self) -> PinArc<T> {
+        self.current.lock().get().clone()
+    }
+
+    /// Replace the current value. Handles previously returned by `load`
+    /// continue to refer to the old value.
+    pub fn store(&self, new: PinArc<T>) {
+        // The old handle may be the last one, so drop it outside the lock
+        drop(self.swap(new));
+    }
+
+    /// Replace the current value, returning a handle to the previous one.
+    pub fn swap(&self, new: PinArc<T>) -> PinArc<T> {
+        mem::replace(self.current.lock().get_mut(), new)
+    }
+
+    /// Replace the current value with `new`, provided it is still the same
+    /// allocation as `current`, and return the previous handle. Otherwise
+    /// nothing is replaced, and `new` is returned as the error.
+    pub fn compare_and_swap(&self, current: &PinArc<T>, new: PinArc<T>) -> Result<PinArc<T>, PinArc<T>> {
+        let mut guard = self.current.lock();
+        if PinArc::ptr_eq(guard.get(), current) {
+            Ok(mem::replace(guard.get_mut(), new))
+        } else {
+            Err(new)
+        }
+    }
+}
+
+impl<T: ?Sized> From<PinArc<T>> for PinArcSwap<T> {
+    #[inline]
+    fn from(initial: PinArc<T>) -> Self {
+        PinArcSwap::new(initial)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PinArcSwap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PinArcSwap").field(&self.load()).finish()
+    }
+}
@@ -0,0 +1,114 @@
+use std::mem::Pin;
+use std::marker::{Unpin, Unsize};
+use std::ops::{Deref, CoerceUnsized};
+use std::fmt;
+use std::boxed;
+
+/// A pinned, uniquely owned heap allocation.
+///
+/// Unlike `PinRc` and `PinArc` there is no reference count or interior
+/// mutability: the owner gets pinned mutable access directly.
+///
+/// This mirrors `std::boxed::PinBox`, and converts to and from it with
+/// `into_std` and `from_std`. It exists so that this crate can offer the
+/// same operations on a uniquely owned value as on its shared handles,
+/// which the std type does not: moving an `Unpin` value out, raw pointer
+/// conversions, and value comparisons and hashing.
+#[derive(Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PinBox<T: ?Sized> {
+    inner: Box<T>
+}
+
+impl<T> PinBox<T> {
+    /// Allocate memory on the heap, move the data into it and pin it.
+    pub fn new(data: T) -> PinBox<T> {
+        PinBox { inner: Box::new(data) }
+    }
+}
+
+impl<T: Unpin> PinBox<T> {
+    pub fn into_inner(this: PinBox<T>) -> T {
+        *this.inner
+    }
+}
+
+impl<T: Unpin + ?Sized> PinBox<T> {
+    pub fn safe_unpin(this: PinBox<T>) -> Box<T> {
+        this.inner
+    }
+}
+
+impl<T: ?Sized> PinBox<T> {
+    pub fn into_raw(this: Self) -> *mut T {
+        Box::into_raw(this.inner)
+    }
+
+    pub unsafe fn from_raw(ptr: *mut T) -> Self {
+        PinBox { inner: Box::from_raw(ptr) }
+    }
+
+    /// Convert this PinBox into an unpinned Box.
+    ///
+    /// This function is unsafe. Users must guarantee that data is never
+    /// moved out of the Box.
+    #[inline]
+    pub unsafe fn unpin(this: PinBox<T>) -> Box<T> {
+        this.inner
+    }
+
+    /// Convert from the equivalent `std::boxed::PinBox`, without moving the
+    /// value.
+    #[inline]
+    pub fn from_std(boxed: boxed::PinBox<T>) -> PinBox<T> {
+        // The value stays in the same allocation, so it remains pinned
+        PinBox { inner: unsafe { boxed::PinBox::unpin(boxed) } }
+    }
+
+    /// Convert into the equivalent `std::boxed::PinBox`, without moving the
+    /// value.
+    #[inline]
+    pub fn into_std(this: PinBox<T>) -> boxed::PinBox<T> {
+        boxed::PinBox::from(this.inner)
+    }
+
+    #[inline]
+    pub fn as_pin(&mut self) -> Pin<T> {
+        unsafe { Pin::new_unchecked(&mut *self.inner) }
+    }
+
+    #[inline]
+    pub unsafe fn get_mut(this: &mut Self) -> &mut T {
+        &mut *this.inner
+    }
+}
+
+impl<T> From<T> for PinBox<T> {
+    #[inline]
+    fn from(t: T) -> Self {
+        PinBox::new(t)
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for PinBox<T> {
+    #[inline]
+    fn from(inner: Box<T>) -> Self {
+        PinBox { inner }
+    }
+}
+
+impl<T: ?Sized> Deref for PinBox<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &*self.inner
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PinBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.inner, f)
+    }
+}
+
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<PinBox<U>> for PinBox<T> {}
@@ -0,0 +1,73 @@
+use std::sync::{Mutex, Condvar, LockResult, PoisonError};
+use std::fmt;
+
+use pin_arc::{PinArc, PinRwLockWriteGuard};
+
+/// A condition variable for waiting on the value in a `PinArc`.
+///
+/// `Condvar` can only be used with a `Mutex`, so this pairs one with a
+/// counter of notifications instead. Waiters register for the next
+/// notification before releasing the value's lock, so a change made and
+/// notified by another thread in the meantime is never missed.
+pub struct PinCondvar {
+    notifications: Mutex<u64>,
+    condvar: Condvar,
+}
+
+impl PinCondvar {
+    pub fn new() -> PinCondvar {
+        PinCondvar { notifications: Mutex::new(0), condvar: Condvar::new() }
+    }
+
+    /// Wake up one thread waiting in `PinArc::wait_write`.
+    pub fn notify_one(&self) {
+        *self.notifications.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        self.condvar.notify_one();
+    }
+
+    /// Wake up all threads waiting in `PinArc::wait_write`.
+    pub fn notify_all(&self) {
+        *self.notifications.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        self.condvar.notify_all();
+    }
+}
+
+impl Default for PinCondvar {
+    fn default() -> PinCondvar {
+        PinCondvar::new()
+    }
+}
+
+impl fmt::Debug for PinCondvar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PinCondvar { .. }")
+    }
+}
+
+impl<T: ?Sized> PinArc<T> {
+    /// Lock for writing once `pred` holds for the value.
+    ///
+    /// The predicate is checked with the write lock held. Whenever it does
+    /// not hold, the lock is released until `condvar` is notified, and
+    /// then retaken. Threads which change the value should notify the
+    /// condvar afterwards. If the lock is poisoned, the poisoned guard is
+    /// returned immediately, without checking the predicate.
+    pub fn wait_write<F>(&self, condvar: &PinCondvar, pred: F) -> LockResult<PinRwLockWriteGuard<T>>
+        where F: Fn(&T) -> bool
+    {
+        loop {
+            let guard = self.write()?;
+            if pred(&*guard) {
+                return Ok(guard);
+            }
+            let mut notifications = condvar.notifications.lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let seen = *notifications;
+            drop(guard);
+            while *notifications == seen {
+                notifications = condvar.condvar.wait(notifications)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+    }
+}
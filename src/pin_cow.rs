@@ -0,0 +1,66 @@
+use std::fmt;
+
+use pin_rc::PinRc;
+
+/// Either a shared reference to a pinned value, or a `PinRc` owning one,
+/// similar to `std::borrow::Cow`.
+///
+/// A `PinRc` can only be read through a borrow of its `RefCell`, so rather
+/// than implementing `Deref`, the value is accessed with `with`.
+pub enum PinCow<'a, T: ?Sized + 'a> {
+    Borrowed(&'a T),
+    Owned(PinRc<T>),
+}
+
+impl<'a, T: ?Sized> PinCow<'a, T> {
+    /// Call `f` with a shared reference to the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is owned and currently mutably borrowed.
+    pub fn with<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        match *self {
+            PinCow::Borrowed(value) => f(value),
+            PinCow::Owned(ref rc) => f(&*rc.borrow()),
+        }
+    }
+
+    pub fn is_owned(&self) -> bool {
+        match *self {
+            PinCow::Borrowed(_) => false,
+            PinCow::Owned(_) => true,
+        }
+    }
+
+    /// Get an owning handle to the value, cloning it into a new allocation
+    /// if it is borrowed.
+    pub fn into_owned(self) -> PinRc<T> where T: Clone {
+        match self {
+            PinCow::Borrowed(value) => PinRc::new(value.clone()),
+            PinCow::Owned(rc) => rc,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> From<&'a T> for PinCow<'a, T> {
+    #[inline]
+    fn from(value: &'a T) -> Self {
+        PinCow::Borrowed(value)
+    }
+}
+
+impl<'a, T: ?Sized> From<PinRc<T>> for PinCow<'a, T> {
+    #[inline]
+    fn from(rc: PinRc<T>) -> Self {
+        PinCow::Owned(rc)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinCow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PinCow::Borrowed(value) => f.debug_tuple("Borrowed").field(&value).finish(),
+            PinCow::Owned(ref rc) => f.debug_tuple("Owned").field(rc).finish(),
+        }
+    }
+}
@@ -1,11 +1,42 @@
 use std::rc::{Rc, Weak};
 use std::cell::{RefCell, Ref, RefMut, BorrowError, BorrowMutError};
-use std::mem::Pin;
-use std::marker::Unpin;
-use std::ops::Deref;
+use std::mem::{self, Pin};
+use std::marker::{Unpin, Unsize};
+use std::ops::{Deref, DerefMut, CoerceUnsized};
+use std::borrow;
+use std::boxed;
+use std::hash::{Hash, Hasher};
 use std::fmt;
+use std::slice;
 
+use pin_arc::{PinArc, alloc_key};
+use pin_box::PinBox;
+use maybe_uninit::MaybeUninit;
+use by_key::SharedHandle;
+
+/// A single-threaded reference counted pointer to a pinned value in a
+/// `RefCell`.
+///
+/// Like `Rc`, `PinRc` is neither `Send` nor `Sync`:
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(pin_rc::PinRc::new(0));
+/// ```
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>(_: T) {}
+/// assert_sync(pin_rc::PinRc::new(0));
+/// ```
+///
+/// Unlike `PinArc`, comparisons between `PinRc`s compare the values they
+/// point to, like `Rc<RefCell<T>>`. This borrows both values, and so will
+/// panic if either is currently mutably borrowed. Use `PinRc::ptr_eq`
+/// to compare handles by identity instead.
+///
+/// `PinRc<T>` has the same layout as `Rc<RefCell<T>>`.
 #[derive(Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(transparent)]
 pub struct PinRc<T: ?Sized> {
     inner: Rc<RefCell<T>>
 }
@@ -22,17 +53,119 @@ pub struct PinRefMut<'a, T: ?Sized + 'a> {
     inner: RefMut<'a, T>
 }
 
+/// An iterator over pinned references to the elements of a mutably
+/// borrowed slice.
+pub struct PinSliceIterMut<'a, T: 'a> {
+    inner: slice::IterMut<'a, T>
+}
+
 impl<T> PinRc<T> {
     /// Allocate memory on the heap, move the data into it and pin it.
     pub fn new(data: T) -> PinRc<T> {
         PinRc { inner: Rc::new(RefCell::new(data)) }
     }
+
+    /// Allocate and pin memory for a value, without initializing it.
+    ///
+    /// The value can then be initialized in place through a mutable borrow,
+    /// for example using `MaybeUninit::pinned_ptr`, before `assume_init`
+    /// is called.
+    pub fn new_uninit() -> PinRc<MaybeUninit<T>> {
+        PinRc::new(MaybeUninit::uninitialized())
+    }
+}
+
+impl<T> PinRc<MaybeUninit<T>> {
+    /// Convert to a `PinRc<T>` once the value has been initialized.
+    ///
+    /// This function is unsafe. Users must guarantee that the value has been
+    /// fully initialized, as it will be treated as a valid `T` from now on.
+    /// All other strong and weak references to the allocation continue to
+    /// refer to it as a `MaybeUninit<T>`, and must not be used to
+    /// uninitialize it.
+    pub unsafe fn assume_init(this: PinRc<MaybeUninit<T>>) -> PinRc<T> {
+        PinRc::from_raw(PinRc::into_raw(this) as *const RefCell<T>)
+    }
+}
+
+impl<T: Unpin> PinRc<T> {
+    /// Returns the inner value, provided this is the only strong reference
+    /// to it. Otherwise the handle is dropped and `None` is returned.
+    pub fn into_inner(this: PinRc<T>) -> Option<T> {
+        Rc::try_unwrap(this.inner).ok().map(RefCell::into_inner)
+    }
+
+    /// Clone the value into a new, independent allocation.
+    ///
+    /// Unlike `clone`, which returns another handle to the same value, the
+    /// result does not share anything with this handle.
+    pub fn deep_clone(this: &Self) -> PinRc<T> where T: Clone {
+        PinRc::new(this.borrow().clone())
+    }
+
+    /// Replace the value with `value`, returning the previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    #[inline]
+    pub fn replace(&self, value: T) -> T {
+        self.inner.replace(value)
+    }
+
+    /// Exchange the values of two allocations. Nothing happens if they are
+    /// the same allocation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either value is currently borrowed.
+    #[inline]
+    pub fn swap(&self, other: &Self) {
+        if !PinRc::ptr_eq(self, other) {
+            self.inner.swap(&other.inner)
+        }
+    }
+
+    /// Move the value out of a `PinBox` into a new `PinRc`.
+    ///
+    /// The value is moved to a new allocation, which is only allowed
+    /// because it is `Unpin`.
+    pub fn from_pin_box(boxed: PinBox<T>) -> PinRc<T> {
+        PinRc::new(PinBox::into_inner(boxed))
+    }
+
+    /// Like `from_pin_box`, but for the standard library's `PinBox`.
+    pub fn from_std_pin_box(boxed: boxed::PinBox<T>) -> PinRc<T> {
+        let boxed: Box<T> = boxed.into();
+        PinRc::new(*boxed)
+    }
+
+    /// Move the value into a new `PinArc`, provided this is the only strong
+    /// reference to it. Otherwise the original `PinRc` is returned.
+    ///
+    /// Unlike the `PinRc`, the resulting `PinArc` is `Send`, so this can be
+    /// used to hand a value over to another thread once it is no longer
+    /// shared. Any weak references will no longer upgrade.
+    pub fn into_arc(this: PinRc<T>) -> Result<PinArc<T>, PinRc<T>>
+        where T: Send + Sync
+    {
+        match Rc::try_unwrap(this.inner) {
+            Ok(cell) => Ok(PinArc::new(cell.into_inner())),
+            Err(inner) => Err(PinRc { inner }),
+        }
+    }
 }
 
 impl<T: Unpin + ?Sized> PinRc<T> {
     pub fn safe_unpin(this: PinRc<T>) -> Rc<RefCell<T>> {
         this.inner
     }
+
+    /// Borrow the underlying `Rc`, without giving up this handle.
+    #[inline]
+    pub fn inner(&self) -> &Rc<RefCell<T>> {
+        &self.inner
+    }
 }
 
 impl<T: ?Sized> PinRc<T> {
@@ -40,10 +173,49 @@ impl<T: ?Sized> PinRc<T> {
         Rc::into_raw(this.inner)
     }
 
+    /// Consume the handle without releasing its reference, returning a
+    /// shared reference to the value which lives forever.
+    ///
+    /// The value is left borrowed for good, so any later attempt to
+    /// mutably borrow it through another handle will fail.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    pub fn leak(this: Self) -> &'static T where T: 'static {
+        let cell: &'static RefCell<T> = unsafe { &*PinRc::into_raw(this) };
+        let borrow = cell.borrow();
+        let value = unsafe { &*(&*borrow as *const T) };
+        mem::forget(borrow);
+        value
+    }
+
     pub unsafe fn from_raw(ptr: *const RefCell<T>) -> Self {
         PinRc { inner: Rc::from_raw(ptr) }
     }
 
+    /// Increment the strong reference count of an allocation, given
+    /// a pointer obtained from `into_raw`.
+    ///
+    /// This function is unsafe. The pointer must have come from `into_raw`,
+    /// and the allocation must still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const RefCell<T>) {
+        let inner = Rc::from_raw(ptr);
+        mem::forget(inner.clone());
+        mem::forget(inner);
+    }
+
+    /// Decrement the strong reference count of an allocation, given
+    /// a pointer obtained from `into_raw`. The value is dropped if this
+    /// was the last strong reference.
+    ///
+    /// This function is unsafe. The pointer must have come from `into_raw`,
+    /// and must own one strong reference to the allocation, which is
+    /// given up by this call.
+    pub unsafe fn decrement_strong_count(ptr: *const RefCell<T>) {
+        drop(Rc::from_raw(ptr));
+    }
+
     /// Convert this PinRc into an unpinned Rc.
     ///
     /// This function is unsafe. Users must guarantee that data is never
@@ -53,11 +225,35 @@ impl<T: ?Sized> PinRc<T> {
         this.inner
     }
 
+    /// Like `unpin`, but only succeeds if there are no other strong or weak
+    /// references to the value. Otherwise the original `PinRc` is returned.
+    ///
+    /// This function is unsafe. The check only guarantees that nothing else
+    /// can still observe the value at its pinned address. Users must still
+    /// guarantee that the value is not relying on its address itself, for
+    /// example because it has not been pinned since it was constructed.
+    /// Values which are `Unpin` can use `safe_unpin` instead.
+    pub unsafe fn unpin_unique(this: PinRc<T>) -> Result<Rc<RefCell<T>>, PinRc<T>> {
+        if PinRc::debug_counts(&this) == (1, 0) {
+            Ok(this.inner)
+        } else {
+            Err(this)
+        }
+    }
+
     #[inline]
     pub fn downgrade(this: &Self) -> PinWeak<T> {
         PinWeak { inner: Rc::downgrade(&this.inner) }
     }
 
+    /// Downgrade, and pass the resulting `PinWeak` to `f`.
+    #[inline]
+    pub fn downgrade_and<R, F>(this: &Self, f: F) -> R
+        where F: FnOnce(PinWeak<T>) -> R
+    {
+        f(PinRc::downgrade(this))
+    }
+
     #[inline]
     pub fn weak_count(this: &Self) -> usize {
         Rc::weak_count(&this.inner)
@@ -68,11 +264,51 @@ impl<T: ?Sized> PinRc<T> {
         Rc::strong_count(&this.inner)
     }
 
+    /// Returns the strong and weak reference counts, in that order.
+    #[inline]
+    pub fn debug_counts(this: &Self) -> (usize, usize) {
+        (PinRc::strong_count(this), PinRc::weak_count(this))
+    }
+
+    /// Panics if there are any other strong or weak references to the value.
+    ///
+    /// This is only checked in debug builds, to help track down reference
+    /// cycles which keep a value alive when it is expected to be unique.
+    #[cfg(debug_assertions)]
+    pub fn assert_unique(this: &Self) {
+        let (strong, weak) = PinRc::debug_counts(this);
+        if strong != 1 || weak != 0 {
+            panic!("PinRc is not unique: {} strong and {} weak references", strong, weak);
+        }
+    }
+
     #[inline]
     pub fn ptr_eq(this: &Self, other: &Self) -> bool {
         Rc::ptr_eq(&this.inner, &other.inner)
     }
 
+    /// Returns true if `weak` refers to the same allocation as this handle.
+    ///
+    /// `weak` is upgraded for the comparison, but as this handle keeps the
+    /// allocation alive, the extra strong reference is only momentary and
+    /// is never the last one. A default `PinWeak` never compares equal.
+    #[inline]
+    pub fn weak_ptr_eq(this: &Self, weak: &PinWeak<T>) -> bool {
+        weak.inner.upgrade().map_or(false, |inner| Rc::ptr_eq(&this.inner, &inner))
+    }
+
+    /// Get a pinned reference to the value without tracking the borrow or
+    /// checking the reference counts.
+    ///
+    /// This function is unsafe. Users must guarantee that the value is not
+    /// accessed through any other `PinRc` or `PinWeak` while the returned
+    /// reference is alive, for example by checking that this is the only
+    /// strong reference and that there are no weak references.
+    #[inline]
+    pub unsafe fn get_mut_unchecked(this: &mut Self) -> Pin<T> {
+        Pin::new_unchecked(&mut *this.inner.as_ptr())
+    }
+
     #[inline]
     pub fn borrow(&self) -> PinRef<T> {
         PinRef { inner: self.inner.borrow() }
@@ -83,6 +319,23 @@ impl<T: ?Sized> PinRc<T> {
         PinRefMut { inner: self.inner.borrow_mut() }
     }
 
+    /// Borrow the value, and project the borrow to part of it.
+    #[inline]
+    pub fn borrow_map<U: ?Sized, F>(&self, f: F) -> PinRef<U>
+        where F: FnOnce(&T) -> &U
+    {
+        PinRef::map(self.borrow(), f)
+    }
+
+    /// Mutably borrow the value, and project the borrow to part of it,
+    /// which remains pinned.
+    #[inline]
+    pub fn borrow_mut_map<U: ?Sized, F>(&self, f: F) -> PinRefMut<U>
+        where F: FnOnce(Pin<T>) -> Pin<U>
+    {
+        PinRefMut::map(self.borrow_mut(), f)
+    }
+
     #[inline]
     pub fn try_borrow(&self) -> Result<PinRef<T>, BorrowError> {
         Ok(PinRef { inner: self.inner.try_borrow()? })
@@ -92,6 +345,47 @@ impl<T: ?Sized> PinRc<T> {
     pub fn try_borrow_mut(&self) -> Result<PinRefMut<T>, BorrowMutError> {
         Ok(PinRefMut { inner: self.inner.try_borrow_mut()? })
     }
+
+    /// Call `f` with a shared reference to the value, unless it is already
+    /// mutably borrowed, in which case `f` is not called.
+    pub fn try_with<R, F>(&self, f: F) -> Result<R, BorrowError>
+        where F: FnOnce(&T) -> R
+    {
+        let borrow = self.try_borrow()?;
+        Ok(f(&*borrow))
+    }
+
+    /// Call `f` with a pinned reference to the value, unless it is already
+    /// borrowed, in which case `f` is not called.
+    ///
+    /// This allows code which may be re-entered, such as event handlers, to
+    /// back off rather than panic.
+    pub fn try_with_mut<R, F>(&self, f: F) -> Result<R, BorrowMutError>
+        where F: FnOnce(Pin<T>) -> R
+    {
+        let mut borrow = self.try_borrow_mut()?;
+        Ok(f(borrow.as_pin()))
+    }
+
+    /// Get a shared reference to the value without recording a borrow.
+    ///
+    /// This function is unsafe. Users must guarantee that the value is not
+    /// mutably borrowed, through this or any other handle, for as long as
+    /// the returned reference is alive. Unlike with `borrow`, nothing will
+    /// stop a `borrow_mut` from succeeding in the meantime:
+    ///
+    /// ```
+    /// # use pin_rc::PinRc;
+    /// let rc = PinRc::new(vec![1, 2, 3]);
+    /// let values = unsafe { rc.borrow_unchecked() };
+    /// // Undefined behaviour: `push` may reallocate while `values` is in use
+    /// // rc.borrow_mut().push(4);
+    /// assert_eq!(values.len(), 3);
+    /// ```
+    #[inline]
+    pub unsafe fn borrow_unchecked(&self) -> &T {
+        &*self.inner.as_ptr()
+    }
 }
 
 impl<T: ?Sized> Clone for PinRc<T> {
@@ -99,8 +393,20 @@ impl<T: ?Sized> Clone for PinRc<T> {
     fn clone(&self) -> Self {
         PinRc { inner: self.inner.clone() }
     }
+
+    /// Does nothing if both handles already point to the same allocation.
+    #[inline]
+    fn clone_from(&mut self, source: &Self) {
+        if !PinRc::ptr_eq(self, source) {
+            *self = source.clone();
+        }
+    }
 }
 
+/// Allows a handle to a sized value to be converted into one to an unsized
+/// value, such as a slice or trait object.
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<PinRc<U>> for PinRc<T> {}
+
 impl<T> From<T> for PinRc<T> {
     #[inline]
     fn from(t: T) -> Self {
@@ -108,6 +414,31 @@ impl<T> From<T> for PinRc<T> {
     }
 }
 
+/// Exposing the `RefCell` would allow the value to be moved out, so this
+/// is only available for `Unpin` values.
+impl<T: Unpin + ?Sized> AsRef<RefCell<T>> for PinRc<T> {
+    #[inline]
+    fn as_ref(&self) -> &RefCell<T> {
+        &*self.inner
+    }
+}
+
+impl<T: Unpin + ?Sized> borrow::Borrow<RefCell<T>> for PinRc<T> {
+    #[inline]
+    fn borrow(&self) -> &RefCell<T> {
+        &*self.inner
+    }
+}
+
+/// Moves the boxed value into a new allocation, so this is only available
+/// for sized `T`.
+impl<T> From<Box<T>> for PinRc<T> {
+    #[inline]
+    fn from(boxed: Box<T>) -> Self {
+        PinRc::new(*boxed)
+    }
+}
+
 impl<T> From<Rc<RefCell<T>>> for PinRc<T> {
     #[inline]
     fn from(inner: Rc<RefCell<T>>) -> Self {
@@ -115,14 +446,82 @@ impl<T> From<Rc<RefCell<T>>> for PinRc<T> {
     }
 }
 
+/// Formats the value, or a placeholder if it is currently mutably borrowed.
+impl<T: ?Sized + fmt::Display> fmt::Display for PinRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner.try_borrow() {
+            Ok(value) => fmt::Display::fmt(&*value, f),
+            Err(_) => f.write_str("<borrowed>"),
+        }
+    }
+}
+
+impl<T: ?Sized> SharedHandle for PinRc<T> {
+    type Target = T;
+
+    #[inline]
+    fn addr(this: &Self) -> usize {
+        alloc_key(&*this.inner)
+    }
+    /// Panics if the value is currently mutably borrowed.
+    #[inline]
+    fn with_value<R, F: FnOnce(&T) -> R>(this: &Self, f: F) -> R {
+        f(&*this.inner.borrow())
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for PinRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ptr: *const RefCell<T> = &*self.inner;
+        fmt::Pointer::fmt(&ptr, f)
+    }
+}
+
 impl<'a, T: ?Sized> PinRef<'a, T> {
     #[inline]
     pub fn clone(this: &Self) -> Self {
         PinRef { inner: Ref::clone(&this.inner) }
     }
+    pub fn map<U: ?Sized, F>(orig: Self, f: F) -> PinRef<'a, U>
+        where F: FnOnce(&T) -> &U
+    {
+        PinRef { inner: Ref::map(orig.inner, f) }
+    }
+
+    /// Call `f` with the borrowed value, then return the borrow unchanged.
+    #[inline]
+    pub fn inspect<F: FnOnce(&T)>(this: Self, f: F) -> Self {
+        f(&*this.inner);
+        this
+    }
+
+    /// Unwrap the underlying `Ref`. A shared borrow can never be used to
+    /// move the value, so this is always safe.
+    #[inline]
+    pub fn into_ref(this: Self) -> Ref<'a, T> {
+        this.inner
+    }
 }
 
-impl<'a, T> Deref for PinRef<'a, T> {
+/// Guards compare and hash by the borrowed value, unlike the handles
+/// they were obtained from.
+impl<'a, T: ?Sized + PartialEq> PartialEq for PinRef<'a, T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        *self.inner == *other.inner
+    }
+}
+
+impl<'a, T: ?Sized + Eq> Eq for PinRef<'a, T> {}
+
+impl<'a, T: ?Sized + Hash> Hash for PinRef<'a, T> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (*self.inner).hash(state)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinRef<'a, T> {
     type Target = T;
 
     #[inline]
@@ -136,10 +535,43 @@ impl<'a, T: ?Sized> PinRefMut<'a, T> {
     pub fn as_pin(&mut self) -> Pin<T> {
         unsafe { Pin::new_unchecked(&mut *self.inner) }
     }
+    /// Re-borrow the guard as a pinned reference. This is the same as
+    /// `as_pin`, and can be called any number of times while the guard is
+    /// held.
+    #[inline]
+    pub fn reborrow(&mut self) -> Pin<T> {
+        self.as_pin()
+    }
+    /// Re-borrow the guard as a shared reference, which cannot be used to
+    /// move the value.
+    #[inline]
+    pub fn reborrow_shared(&self) -> &T {
+        &*self.inner
+    }
     #[inline]
     pub unsafe fn get_mut(this: &mut Self) -> &mut T {
         &mut *this.inner
     }
+    /// Project the borrow to part of the value, which remains pinned.
+    ///
+    /// The projection is given a pinned reference which only lives for the
+    /// duration of the call, rather than for `'a`, so it cannot keep hold
+    /// of it after the borrow is released:
+    ///
+    /// ```compile_fail
+    /// # #![feature(pin)]
+    /// # extern crate pin_rc;
+    /// # use std::mem::Pin;
+    /// # use pin_rc::{PinRc, PinRefMut};
+    /// # fn main() {
+    /// let rc = PinRc::new((1, 2));
+    /// let mut escaped: Option<Pin<(i32, i32)>> = None;
+    /// PinRefMut::map::<(i32, i32), _>(rc.borrow_mut(), |pin| {
+    ///     escaped = Some(pin);
+    ///     unimplemented!()
+    /// });
+    /// # }
+    /// ```
     pub fn map<U: ?Sized, F>(orig: Self, f: F) -> PinRefMut<'a, U>
         where F: FnOnce(Pin<T>) -> Pin<U>
     {
@@ -150,9 +582,32 @@ impl<'a, T: ?Sized> PinRefMut<'a, T> {
             unsafe { &mut *(u as *mut U) }
         }) }
     }
+
+    /// Call `f` with a shared reference to the borrowed value, then return
+    /// the borrow unchanged. This is useful for debugging chains of `map`s.
+    #[inline]
+    pub fn inspect<F: FnOnce(&T)>(this: Self, f: F) -> Self {
+        f(&*this.inner);
+        this
+    }
+
+    /// Like `map`, but the projection may fail, in which case the original
+    /// borrow is returned intact.
+    pub fn filter_map<U: ?Sized, F>(mut orig: Self, f: F) -> Result<PinRefMut<'a, U>, Self>
+        where F: FnOnce(Pin<T>) -> Option<Pin<U>>
+    {
+        let projected = {
+            let pin_v = unsafe { Pin::new_unchecked(&mut *orig.inner) };
+            f(pin_v).map(|mut pin_u| unsafe { Pin::get_mut(&mut pin_u) as *mut U })
+        };
+        match projected {
+            Some(u) => Ok(PinRefMut { inner: RefMut::map(orig.inner, |_| unsafe { &mut *u }) }),
+            None => Err(orig),
+        }
+    }
 }
 
-impl<'a, T> Deref for PinRefMut<'a, T> {
+impl<'a, T: ?Sized> Deref for PinRefMut<'a, T> {
     type Target = T;
 
     #[inline]
@@ -161,11 +616,76 @@ impl<'a, T> Deref for PinRefMut<'a, T> {
     }
 }
 
+/// Values which are `Unpin` may be freely moved, so no pinning is needed
+/// to mutate them.
+impl<'a, T: ?Sized + Unpin> PinRefMut<'a, T> {
+    /// Unwrap the underlying `RefMut`. This requires `T: Unpin`, as a
+    /// `RefMut` can be used to move the value out.
+    #[inline]
+    pub fn into_ref_mut(this: Self) -> RefMut<'a, T> {
+        this.inner
+    }
+}
+
+impl<'a, T: ?Sized + Unpin> DerefMut for PinRefMut<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.inner
+    }
+}
+
+/// A shared reference cannot be used to move a value, so the elements of a
+/// borrowed slice are simply iterated by reference.
+impl<'b, 'a, T> IntoIterator for &'b PinRef<'a, [T]> {
+    type Item = &'b T;
+    type IntoIter = slice::Iter<'b, T>;
+
+    #[inline]
+    fn into_iter(self) -> slice::Iter<'b, T> {
+        self.inner.iter()
+    }
+}
+
+impl<'b, 'a, T> IntoIterator for &'b mut PinRefMut<'a, [T]> {
+    type Item = Pin<'b, T>;
+    type IntoIter = PinSliceIterMut<'b, T>;
+
+    #[inline]
+    fn into_iter(self) -> PinSliceIterMut<'b, T> {
+        PinSliceIterMut { inner: self.inner.iter_mut() }
+    }
+}
+
+impl<'a, T> Iterator for PinSliceIterMut<'a, T> {
+    type Item = Pin<'a, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Pin<'a, T>> {
+        // The slice cannot be resized through a pinned borrow, so its
+        // elements never move
+        self.inner.next().map(|value| unsafe { Pin::new_unchecked(value) })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
 impl<T: ?Sized> PinWeak<T> {
     #[inline]
     pub fn upgrade(&self) -> Option<PinRc<T>> {
         self.inner.upgrade().map(|inner| PinRc { inner })
     }
+
+    /// Upgrade if the value is still alive, or otherwise call `f` to
+    /// construct a replacement.
+    #[inline]
+    pub fn upgrade_or_else<F>(&self, f: F) -> PinRc<T>
+        where F: FnOnce() -> PinRc<T>
+    {
+        self.upgrade().unwrap_or_else(f)
+    }
 }
 
 impl<T: ?Sized> Clone for PinWeak<T> {
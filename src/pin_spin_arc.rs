@@ -0,0 +1,162 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering, spin_loop_hint};
+use std::cell::UnsafeCell;
+use std::mem::Pin;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// A minimal spin lock. Waiting threads busy-wait rather than sleeping.
+pub(crate) struct SpinLock<T: ?Sized> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>
+}
+
+unsafe impl<T: ?Sized + Send> Send for SpinLock<T> {}
+unsafe impl<T: ?Sized + Send> Sync for SpinLock<T> {}
+
+pub(crate) struct SpinLockGuard<'a, T: ?Sized + 'a> {
+    lock: &'a SpinLock<T>,
+    // Like `MutexGuard`, the guard is neither `Send` nor `Sync` by default:
+    // `SpinLock<T>` is `Sync` for any `T: Send`, so sharing a reference to
+    // the guard must additionally require `T: Sync`.
+    _marker: PhantomData<*mut ()>
+}
+
+unsafe impl<'a, T: ?Sized + Sync> Sync for SpinLockGuard<'a, T> {}
+
+impl<T> SpinLock<T> {
+    pub(crate) fn new(value: T) -> SpinLock<T> {
+        SpinLock { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    pub(crate) fn lock(&self) -> SpinLockGuard<T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop_hint();
+            }
+        }
+    }
+
+    pub(crate) fn try_lock(&self) -> Option<SpinLockGuard<T>> {
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            Some(SpinLockGuard { lock: self, _marker: PhantomData })
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T: ?Sized> SpinLockGuard<'a, T> {
+    #[inline]
+    pub(crate) fn get(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Like `PinArc`, but protected by a spin lock rather than an `RwLock`.
+///
+/// Threads waiting for the lock busy-wait, so this should only be used
+/// for very short critical sections with little contention, for example
+/// resuming a generator that yields almost immediately. For anything
+/// longer, prefer `PinArc`. The lock is not poisoned by panics.
+pub struct PinSpinArc<T: ?Sized> {
+    inner: Arc<SpinLock<T>>
+}
+
+/// A guard giving pinned access to the value of a `PinSpinArc`.
+///
+/// Like `MutexGuard`, the guard cannot be sent to another thread, and can
+/// only be shared between threads if the value is `Sync`:
+///
+/// ```compile_fail
+/// # use pin_rc::PinSpinArc;
+/// use std::cell::Cell;
+/// use std::thread;
+///
+/// let arc = PinSpinArc::new(Cell::new(0));
+/// let guard: &'static _ = Box::leak(Box::new(arc.lock()));
+/// thread::spawn(move || guard.set(1));
+/// ```
+pub struct PinSpinLockGuard<'a, T: ?Sized + 'a> {
+    inner: SpinLockGuard<'a, T>
+}
+
+impl<T> PinSpinArc<T> {
+    /// Allocate memory on the heap, move the data into it and pin it.
+    pub fn new(data: T) -> PinSpinArc<T> {
+        PinSpinArc { inner: Arc::new(SpinLock::new(data)) }
+    }
+}
+
+impl<T: ?Sized> PinSpinArc<T> {
+    #[inline]
+    pub fn strong_count(this: &Self) -> usize {
+        Arc::strong_count(&this.inner)
+    }
+
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.inner, &other.inner)
+    }
+
+    /// Acquire the lock, spinning until it is available.
+    #[inline]
+    pub fn lock(&self) -> PinSpinLockGuard<T> {
+        PinSpinLockGuard { inner: self.inner.lock() }
+    }
+
+    #[inline]
+    pub fn try_lock(&self) -> Option<PinSpinLockGuard<T>> {
+        self.inner.try_lock().map(|inner| PinSpinLockGuard { inner })
+    }
+}
+
+impl<T: ?Sized> Clone for PinSpinArc<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        PinSpinArc { inner: self.inner.clone() }
+    }
+}
+
+impl<T> From<T> for PinSpinArc<T> {
+    #[inline]
+    fn from(t: T) -> Self {
+        PinSpinArc::new(t)
+    }
+}
+
+impl<'a, T: ?Sized> PinSpinLockGuard<'a, T> {
+    #[inline]
+    pub fn as_pin(&mut self) -> Pin<T> {
+        unsafe { Pin::new_unchecked(self.inner.get_mut()) }
+    }
+    #[inline]
+    pub unsafe fn get_mut(this: &mut Self) -> &mut T {
+        this.inner.get_mut()
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinSpinLockGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.inner.get()
+    }
+}
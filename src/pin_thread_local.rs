@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, ThreadId};
+use std::fmt;
+
+use pin_arc::PinArc;
+
+/// Hands out a separate `PinArc` for each thread, constructed on first use
+/// from a shared factory.
+///
+/// Each thread works with its own allocation, so threads never contend on
+/// each other's values, while still using the familiar handle type. The
+/// table of handles is locked briefly on each call to `get`.
+///
+/// Handles are kept until the `PinThreadLocalArc` itself is dropped, even
+/// after the thread they belong to has exited.
+pub struct PinThreadLocalArc<T> {
+    factory: Box<Fn() -> T + Send + Sync>,
+    handles: Mutex<HashMap<ThreadId, PinArc<T>>>,
+}
+
+impl<T> PinThreadLocalArc<T> {
+    pub fn new<F>(factory: F) -> PinThreadLocalArc<T>
+        where F: Fn() -> T + Send + Sync + 'static
+    {
+        PinThreadLocalArc { factory: Box::new(factory), handles: Mutex::new(HashMap::new()) }
+    }
+
+    /// Get the current thread's handle, constructing the value if this
+    /// thread has not called `get` before.
+    pub fn get(&self) -> PinArc<T> {
+        let id = thread::current().id();
+        if let Some(handle) = self.handles.lock().unwrap_or_else(PoisonError::into_inner).get(&id) {
+            return handle.clone();
+        }
+        // Run the factory without holding the lock, in case it calls `get`
+        let handle = PinArc::new((self.factory)());
+        self.handles.lock().unwrap_or_else(PoisonError::into_inner)
+            .entry(id).or_insert(handle).clone()
+    }
+}
+
+impl<T> fmt::Debug for PinThreadLocalArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("PinThreadLocalArc { .. }")
+    }
+}
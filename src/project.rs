@@ -0,0 +1,201 @@
+use std::mem::Pin;
+
+use pin_rc::{PinRef, PinRefMut};
+use pin_arc::{
+    PinRwLockReadGuard, PinRwLockWriteGuard, MappedPinRwLockReadGuard, MappedPinRwLockWriteGuard
+};
+
+/// A guard which can be projected to a field of the value it guards, as
+/// used by `pin_rc_project!`.
+///
+/// Guards with pinned access to the value project using `pinned`, so the
+/// field remains pinned, and guards with shared access project using
+/// `shared`. Implemented by `PinRefMut` and `PinRwLockWriteGuard`, and by
+/// `PinRef` and `PinRwLockReadGuard`.
+pub trait PinProject<'a, S: ?Sized + 'a, F: ?Sized + 'a> {
+    type Output;
+    fn project<P, R>(this: Self, pinned: P, shared: R) -> Self::Output
+        where P: FnOnce(Pin<S>) -> Pin<F>, R: FnOnce(&S) -> &F;
+}
+
+impl<'a, S: ?Sized + 'a, F: ?Sized + 'a> PinProject<'a, S, F> for PinRefMut<'a, S> {
+    type Output = PinRefMut<'a, F>;
+
+    #[inline]
+    fn project<P, R>(this: Self, pinned: P, _shared: R) -> PinRefMut<'a, F>
+        where P: FnOnce(Pin<S>) -> Pin<F>, R: FnOnce(&S) -> &F
+    {
+        PinRefMut::map(this, pinned)
+    }
+}
+
+impl<'a, S: ?Sized + 'a, F: ?Sized + 'a> PinProject<'a, S, F> for PinRwLockWriteGuard<'a, S> {
+    type Output = MappedPinRwLockWriteGuard<'a, S, F>;
+
+    #[inline]
+    fn project<P, R>(this: Self, pinned: P, _shared: R) -> MappedPinRwLockWriteGuard<'a, S, F>
+        where P: FnOnce(Pin<S>) -> Pin<F>, R: FnOnce(&S) -> &F
+    {
+        PinRwLockWriteGuard::map(this, pinned)
+    }
+}
+
+impl<'a, S: ?Sized + 'a, F: ?Sized + 'a> PinProject<'a, S, F> for PinRef<'a, S> {
+    type Output = PinRef<'a, F>;
+
+    #[inline]
+    fn project<P, R>(this: Self, _pinned: P, shared: R) -> PinRef<'a, F>
+        where P: FnOnce(Pin<S>) -> Pin<F>, R: FnOnce(&S) -> &F
+    {
+        PinRef::map(this, shared)
+    }
+}
+
+impl<'a, S: ?Sized + 'a, F: ?Sized + 'a> PinProject<'a, S, F> for PinRwLockReadGuard<'a, S> {
+    type Output = MappedPinRwLockReadGuard<'a, S, F>;
+
+    #[inline]
+    fn project<P, R>(this: Self, _pinned: P, shared: R) -> MappedPinRwLockReadGuard<'a, S, F>
+        where P: FnOnce(Pin<S>) -> Pin<F>, R: FnOnce(&S) -> &F
+    {
+        PinRwLockReadGuard::map(this, shared)
+    }
+}
+
+/// A guard with pinned access to a value, whose `Unpin` fields can be
+/// borrowed mutably, as used by `pin_rc_project!`. Implemented by
+/// `PinRefMut` and `PinRwLockWriteGuard`.
+pub trait PinProjectUnpinned<S: ?Sized> {
+    /// Get a mutable reference to the guarded value.
+    ///
+    /// This function is unsafe. The reference must only be used to reach
+    /// fields which are `Unpin`, and must not be used to move the value.
+    unsafe fn get_mut(this: &mut Self) -> &mut S;
+}
+
+impl<'a, S: ?Sized + 'a> PinProjectUnpinned<S> for PinRefMut<'a, S> {
+    #[inline]
+    unsafe fn get_mut(this: &mut Self) -> &mut S {
+        PinRefMut::get_mut(this)
+    }
+}
+
+impl<'a, S: ?Sized + 'a> PinProjectUnpinned<S> for PinRwLockWriteGuard<'a, S> {
+    #[inline]
+    unsafe fn get_mut(this: &mut Self) -> &mut S {
+        PinRwLockWriteGuard::get_mut(this)
+    }
+}
+
+/// Generates projections from the guards of a struct to its fields.
+///
+/// For each field marked `#[unsafe_pinned]`, an associated function with the
+/// same name as the field is generated, which converts a guard of the
+/// struct into a guard of the field:
+///
+/// - a `PinRefMut` into a `PinRefMut`, and a `PinRwLockWriteGuard` into a
+///   `MappedPinRwLockWriteGuard`, through which the field remains pinned;
+/// - a `PinRef` into a `PinRef`, and a `PinRwLockReadGuard` into a
+///   `MappedPinRwLockReadGuard`, which give shared access.
+///
+/// Unmarked fields must be `Unpin`, and the generated function instead
+/// returns a plain mutable reference to the field, borrowed from a
+/// `PinRefMut` or `PinRwLockWriteGuard`. Shared access to those fields is
+/// already possible through `Deref`.
+///
+/// Marking a field as pinned is only sound if the struct never moves out of
+/// that field: it must not implement `Drop` in a way that does, nor
+/// implement `Unpin` unless the field is `Unpin` too.
+///
+/// ```
+/// #![feature(pin)]
+/// #[macro_use]
+/// extern crate pin_rc;
+///
+/// use pin_rc::{PinRc, PinRef, PinRefMut};
+///
+/// struct Node {
+///     value: String,
+///     visits: u32,
+/// }
+///
+/// pin_rc_project! {
+///     Node => #[unsafe_pinned] value: String, visits: u32
+/// }
+///
+/// fn main() {
+///     let node = PinRc::new(Node { value: "a".into(), visits: 0 });
+///     {
+///         let mut borrow = node.borrow_mut();
+///         *Node::visits(&mut borrow) += 1;
+///         let value: PinRefMut<String> = Node::value(borrow);
+///         assert_eq!(*value, "a");
+///     }
+///     let value: PinRef<String> = Node::value(node.borrow());
+///     assert_eq!(*value, "a");
+///     assert_eq!(node.borrow().visits, 1);
+/// }
+/// ```
+///
+/// The same functions project the guards of a `PinArc`:
+///
+/// ```
+/// #![feature(pin)]
+/// #[macro_use]
+/// extern crate pin_rc;
+///
+/// use pin_rc::PinArc;
+///
+/// struct Counter {
+///     name: String,
+///     count: u32,
+/// }
+///
+/// pin_rc_project! {
+///     Counter => #[unsafe_pinned] name: String, count: u32
+/// }
+///
+/// fn main() {
+///     let counter = PinArc::new(Counter { name: "hits".into(), count: 0 });
+///     {
+///         let mut guard = counter.write().unwrap();
+///         *Counter::count(&mut guard) += 1;
+///         let mut name = Counter::name(guard);
+///         name.as_pin().push('!');
+///     }
+///     let name = Counter::name(counter.read().unwrap());
+///     assert_eq!(*name, "hits!");
+///     assert_eq!(counter.read().unwrap().count, 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! pin_rc_project {
+    ($name:ty => $($(#[$attr:ident])* $field:ident : $field_ty:ty),* $(,)*) => {
+        impl $name {
+            $(pin_rc_project!(@field $name, [$($attr)*] $field : $field_ty);)*
+        }
+    };
+    (@field $name:ty, [unsafe_pinned] $field:ident : $field_ty:ty) => {
+        #[allow(dead_code)]
+        pub fn $field<'a, G>(this: G) -> <G as $crate::PinProject<'a, $name, $field_ty>>::Output
+            where G: $crate::PinProject<'a, $name, $field_ty>
+        {
+            $crate::PinProject::project(
+                this,
+                |mut pin| unsafe {
+                    let value: *mut $name = ::std::mem::Pin::get_mut(&mut pin);
+                    ::std::mem::Pin::new_unchecked(&mut (*value).$field)
+                },
+                |value| &value.$field,
+            )
+        }
+    };
+    (@field $name:ty, [] $field:ident : $field_ty:ty) => {
+        #[allow(dead_code)]
+        pub fn $field<'b, G>(this: &'b mut G) -> &'b mut $field_ty
+            where G: $crate::PinProjectUnpinned<$name>, $field_ty: ::std::marker::Unpin
+        {
+            unsafe { &mut $crate::PinProjectUnpinned::get_mut(this).$field }
+        }
+    };
+}
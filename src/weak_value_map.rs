@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::fmt;
+
+use pin_rc::{self, PinRc};
+use pin_arc::{self, PinArc};
+
+/// A weak reference which can be created from, and upgraded back into, a
+/// strong handle. Implemented by the weak references of both `PinRc` and
+/// `PinArc`.
+pub trait WeakHandle: Sized {
+    type Strong;
+    fn downgrade(strong: &Self::Strong) -> Self;
+    fn upgrade(&self) -> Option<Self::Strong>;
+}
+
+impl<T: ?Sized> WeakHandle for pin_rc::PinWeak<T> {
+    type Strong = PinRc<T>;
+
+    #[inline]
+    fn downgrade(strong: &PinRc<T>) -> Self {
+        PinRc::downgrade(strong)
+    }
+    #[inline]
+    fn upgrade(&self) -> Option<PinRc<T>> {
+        pin_rc::PinWeak::upgrade(self)
+    }
+}
+
+impl<T: ?Sized> WeakHandle for pin_arc::PinWeak<T> {
+    type Strong = PinArc<T>;
+
+    #[inline]
+    fn downgrade(strong: &PinArc<T>) -> Self {
+        PinArc::downgrade(strong)
+    }
+    #[inline]
+    fn upgrade(&self) -> Option<PinArc<T>> {
+        pin_arc::PinWeak::upgrade(self)
+    }
+}
+
+/// A map which holds its values weakly, so that it does not keep them alive.
+///
+/// This is useful as a cache of values which are owned elsewhere. Entries
+/// whose values have been dropped are removed lazily, when they are looked
+/// up, or all at once by `retain_live`.
+pub struct WeakValueMap<K, W> {
+    entries: HashMap<K, W>
+}
+
+/// A `WeakValueMap` holding the values of `PinRc`s.
+pub type RcWeakValueMap<K, V> = WeakValueMap<K, pin_rc::PinWeak<V>>;
+
+/// A `WeakValueMap` holding the values of `PinArc`s.
+pub type ArcWeakValueMap<K, V> = WeakValueMap<K, pin_arc::PinWeak<V>>;
+
+impl<K: Hash + Eq, W: WeakHandle> WeakValueMap<K, W> {
+    pub fn new() -> WeakValueMap<K, W> {
+        WeakValueMap { entries: HashMap::new() }
+    }
+
+    /// Store a weak reference to `value` under `key`, replacing any previous
+    /// entry.
+    pub fn insert(&mut self, key: K, value: &W::Strong) {
+        self.entries.insert(key, W::downgrade(value));
+    }
+
+    /// Get a strong handle to the value stored under `key`, if it is still
+    /// alive. If it has been dropped, the entry is removed.
+    pub fn get(&mut self, key: &K) -> Option<W::Strong> {
+        let value = match self.entries.get(key) {
+            Some(weak) => weak.upgrade(),
+            None => return None,
+        };
+        if value.is_none() {
+            self.entries.remove(key);
+        }
+        value
+    }
+
+    /// Remove all entries whose values have been dropped.
+    pub fn retain_live(&mut self) {
+        self.entries.retain(|_, weak| weak.upgrade().is_some());
+    }
+
+    /// The number of entries, including any whose values have been dropped
+    /// but not yet removed.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: Hash + Eq, W: WeakHandle> Default for WeakValueMap<K, W> {
+    fn default() -> WeakValueMap<K, W> {
+        WeakValueMap::new()
+    }
+}
+
+impl<K: Hash + Eq, W> fmt::Debug for WeakValueMap<K, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WeakValueMap {{ len: {} }}", self.entries.len())
+    }
+}